@@ -61,6 +61,8 @@ impl SeaBackgroundRenderGraphBuilder for RenderGraph {
 pub struct TileUv {
     pub uv: Vec2,
     pub scale: f32,
+    //multiplies the color of the tiles, darkens the sea at night
+    pub tint: Color,
 }
 #[derive(Bundle)]
 pub(crate) struct BackgroundBundle {
@@ -101,6 +103,7 @@ impl Default for BackgroundBundle {
             tile_uv: TileUv {
                 uv: Default::default(),
                 scale: 1.,
+                tint: Color::WHITE,
             },
             flag: BgFlag,
         }
@@ -7,11 +7,15 @@ layout(set=2, binding = 0) uniform TileUv_uv {
 layout(set=2, binding = 2) uniform TileUv_scale {
     float scale;
 };
+layout(set=2, binding = 3) uniform TileUv_tint {
+    vec4 tint;
+};
 layout(set = 3, binding = 0) uniform texture2D TextureAtlas_texture;
 layout(set = 3, binding = 1) uniform sampler TextureAtlas_texture_sampler;
 void main() {
     vec2 screen_offset = mod(world_position.xy/scale , 64.) / vec2(192., 64.);
-    o_Target = texture(
+    vec4 color = texture(
         sampler2D(TextureAtlas_texture, TextureAtlas_texture_sampler),
         uv+screen_offset);
+    o_Target = vec4(color.rgb * tint.rgb, color.a);
 }
\ No newline at end of file
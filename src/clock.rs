@@ -0,0 +1,146 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    background::TileUv,
+    loading::GameState,
+    sea::{loader::BiomeConfig, SeaPaused},
+};
+
+pub struct WorldClockPlugin;
+impl Plugin for WorldClockPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<WorldClock>()
+            .add_startup_system(setup.system())
+            .on_state_update(GameState::STAGE, GameState::Sea, clock_system.system())
+            .on_state_update(GameState::STAGE, GameState::Land, clock_system.system())
+            .on_state_update(GameState::STAGE, GameState::Sea, tint_system.system())
+            .on_state_update(GameState::STAGE, GameState::Land, tint_system.system());
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClockConfig {
+    //length of a whole day, in seconds
+    pub day_length: f32,
+    //the hour when the game starts
+    pub start_hour: f32,
+    //part of the sight left in the middle of the night
+    pub night_visibility: f32,
+    //color the world is multiplied by at night
+    pub night_tint: (f32, f32, f32),
+}
+impl Default for ClockConfig {
+    fn default() -> Self {
+        ClockConfig {
+            day_length: 600.,
+            start_hour: 8.,
+            night_visibility: 0.5,
+            night_tint: (0.3, 0.35, 0.6),
+        }
+    }
+}
+
+fn read_clock_config() -> ClockConfig {
+    let clock_config_string =
+        std::fs::read_to_string("config/clock.ron").expect("clock config file not found");
+    ron::from_str(&clock_config_string).expect("syntax error on clock config file")
+}
+
+//The time of day in the game world, it only passes at sea and on land.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct WorldClock {
+    pub config: ClockConfig,
+    //days since the start of the game
+    pub day: u32,
+    //part of the current day, 0 is midnight
+    pub time: f32,
+}
+
+impl WorldClock {
+    pub fn new(config: ClockConfig) -> Self {
+        WorldClock {
+            time: config.start_hour / 24.,
+            config,
+            day: 0,
+        }
+    }
+
+    fn tick(&mut self, delta_seconds: f32) {
+        self.time += delta_seconds / self.config.day_length;
+        while self.time >= 1. {
+            self.time -= 1.;
+            self.day += 1;
+        }
+    }
+
+    pub fn hour(&self) -> f32 {
+        self.time * 24.
+    }
+
+    //1 in full day, 0 in full night, the sun rises from 5 to 7 and sets from 18 to 20
+    pub fn daylight(&self) -> f32 {
+        let hour = self.hour();
+        let ramp = |from: f32| {
+            let t = ((hour - from) / 2.).max(0.).min(1.);
+            0.5 - 0.5 * (t * PI).cos()
+        };
+        ramp(5.) - ramp(18.)
+    }
+
+    pub fn is_night(&self) -> bool {
+        self.daylight() < 0.5
+    }
+
+    //how far one sees, relative to the full day
+    pub fn visibility(&self) -> f32 {
+        let night = self.config.night_visibility;
+        night + (1. - night) * self.daylight()
+    }
+
+    pub fn tint(&self) -> Color {
+        let daylight = self.daylight();
+        let (r, g, b) = self.config.night_tint;
+        let mix = |night: f32| night + (1. - night) * daylight;
+        Color::rgb(mix(r), mix(g), mix(b))
+    }
+}
+
+fn setup(mut clock: ResMut<WorldClock>) {
+    *clock = WorldClock::new(read_clock_config());
+}
+
+fn clock_system(time: Res<Time>, mut clock: ResMut<WorldClock>, paused: Res<SeaPaused>) {
+    if paused.0 {
+        return;
+    }
+    clock.tick(time.delta_seconds());
+}
+
+//Darkens the sea and the islands at night.
+fn tint_system(
+    clock: Res<WorldClock>,
+    biomes: Res<BiomeConfig>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut background_query: Query<&mut TileUv>,
+    mut last_tint: Local<Option<Color>>,
+) {
+    let tint = clock.tint();
+    for mut tile_uv in background_query.iter_mut() {
+        tile_uv.tint = tint;
+    }
+    //the materials are shared by all the islands of a biome
+    if *last_tint == Some(tint) {
+        return;
+    }
+    *last_tint = Some(tint);
+    for (assets, _) in biomes.0.iter() {
+        for handle in [&assets.sea_material, &assets.land_material].iter() {
+            if let Some(material) = materials.get_mut(*handle) {
+                material.color = tint;
+            }
+        }
+    }
+}
@@ -12,8 +12,6 @@ pub(crate) struct LandHandles {
     pub player_sword: Handle<TextureAtlas>,
     pub player_gun: Handle<TextureAtlas>,
     pub player_sword_collisions: Handle<TextureAtlas>,
-    pub tiles: Handle<TextureAtlas>,
-    pub island_material: Handle<ColorMaterial>,
     pub sea_sheet: Handle<TextureAtlas>,
     pub bullet_material: Handle<ColorMaterial>,
 }
@@ -69,18 +67,6 @@ fn setup(
     let texture_atlas_handle = texture_atlases.add(texture_atlas);
     handles.player_sword_collisions = texture_atlas_handle;
 
-    let texture_handle_islands_spritesheet = asset_server.load("sprites/sea/sheet2.png");
-    let islands_atlas = TextureAtlas::from_grid_with_padding(
-        texture_handle_islands_spritesheet,
-        Vec2::new(16., 16.),
-        27,
-        7,
-        Vec2::new(1., 1.),
-    );
-    handles.island_material = materials.add(ColorMaterial::texture(islands_atlas.texture.clone()));
-    let texture_atlas_handle = texture_atlases.add(islands_atlas);
-    handles.tiles = texture_atlas_handle;
-
     let texture_handle_sea_spritesheet = asset_server.load("sprites/sea/seaTileSheet.png");
 
     let sea_atlas =
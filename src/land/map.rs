@@ -3,7 +3,13 @@ use std::time::Duration;
 use crate::{
     background::{BackgroundBundle, TileUv},
     loading::GameState,
-    sea::{map::Islands, player::PlayerPositionUpdate},
+    sea::{
+        loader::BiomeConfig,
+        map::{IslandDeltas, Islands},
+        player::PlayerPositionUpdate,
+        worldgen::{island_mesh, AtlasLayout, IslandId},
+        TILE_SIZE,
+    },
     util::SeededHasher,
 };
 use bevy::{prelude::*, render::camera::Camera};
@@ -29,11 +35,11 @@ impl Plugin for LandMapPlugin {
 }
 #[derive(Default)]
 pub struct CurrentIsland {
-    pub id: u64,
+    pub id: IslandId,
     pub entrance: (i32, i32),
 }
 pub struct LoadIslandEvent {
-    pub island_id: u64,
+    pub island_id: IslandId,
 }
 
 fn load_island(
@@ -41,18 +47,30 @@ fn load_island(
     sea_player_pos: Res<PlayerPositionUpdate>,
     mut islands: ResMut<Islands>,
     handles: Res<LandHandles>,
+    biomes: Res<BiomeConfig>,
+    mut deltas: ResMut<IslandDeltas>,
+    atlases: Res<Assets<TextureAtlas>>,
     mut meshes: ResMut<Assets<Mesh>>,
 ) {
-    let island = &mut islands.0[sea_player_pos.island_id.unwrap() as usize];
+    let island = islands
+        .0
+        .get_mut(&sea_player_pos.island_id.unwrap())
+        .unwrap();
+    deltas.0.entry(island.id).or_default().visited = true;
+    //the land view uses the land sheet of the biome, so it gets its own mesh
+    let biome_assets = &biomes.0[island.biome].0;
+    let atlas = atlases.get(biome_assets.land_sheet.clone()).unwrap();
+    let tile_size = Vec2::new(TILE_SIZE as f32, TILE_SIZE as f32);
+    let mesh = island_mesh(&island.tiles, &AtlasLayout::from(atlas), tile_size);
     commands
         .spawn(super::super::sea::map::IslandBundle {
-            mesh: island.mesh.clone(),
+            mesh: meshes.add(mesh),
             transform: Transform {
                 translation: Vec3::new(0., 0., 3.),
                 scale: Vec3::new(LAND_SCALING, LAND_SCALING, 1.),
                 ..Default::default()
             },
-            material: handles.island_material.clone(),
+            material: biome_assets.land_material.clone(),
             ..Default::default()
         })
         .with(UnloadLandFlag);
@@ -67,6 +85,7 @@ fn load_island(
             tile_uv: TileUv {
                 uv: Vec2::new(0.0, 0.0),
                 scale: 2. * LAND_SCALING,
+                tint: Color::WHITE,
             },
             ..Default::default()
         })
@@ -100,12 +119,29 @@ fn move_anim_bg(
 
 fn generate_islands_features(
     mut islands: ResMut<Islands>,
-    mut id: Local<usize>,
     hasher: Res<SeededHasher>,
     mobs_config: Res<MobsConfig>,
+    biomes: Res<BiomeConfig>,
+    deltas: Res<IslandDeltas>,
 ) {
-    for i in *id..islands.0.len() {
-        generate_mobs(&mobs_config, &mut islands.0[i], hasher.get_hasher())
+    for island in islands.0.values_mut() {
+        if island.features_generated {
+            continue;
+        }
+        let biome = &biomes.0[island.biome].1;
+        generate_mobs(&mobs_config, island, &biome.name, hasher.get_hasher());
+        //the mobs the player left behind the last time the island was in memory
+        if let Some(mobs) = deltas.0.get(&island.id).and_then(|d| d.mobs.as_ref()) {
+            let generated = std::mem::take(&mut island.mobs);
+            island.mobs = generated
+                .into_iter()
+                .filter_map(|(mob, mut transform)| {
+                    let (_, translation) = mobs.iter().find(|(id, _)| *id == mob.id)?;
+                    transform.translation = (*translation).into();
+                    Some((mob, transform))
+                })
+                .collect();
+        }
+        island.features_generated = true;
     }
-    *id = islands.0.len();
 }
@@ -8,6 +8,7 @@ use seahash::SeaHasher;
 use serde::{Deserialize, Serialize};
 
 use crate::{
+    clock::WorldClock,
     loading::GameState,
     sea::{
         map::{Islands, TileKind},
@@ -39,11 +40,14 @@ impl Plugin for LandMobsPlugin {
 
 #[derive(Default, Clone)]
 pub struct Mob {
+    //index of the mob in the generation order of its island
+    pub id: u32,
     pub kind: String,
     pub material: Handle<ColorMaterial>,
     pub speed: f32,
     pub pathfinder: Option<Pathfinder>,
     pub collider: ColliderType,
+    pub time: SpawnTime,
 }
 
 impl Debug for Mob {
@@ -63,6 +67,29 @@ pub struct SpawnConfig {
     pub biome: String,
     pub tile_kind: TileKind,
     pub rate: f32,
+    #[serde(default)]
+    pub time: SpawnTime,
+}
+//When a mob is out on its island.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum SpawnTime {
+    Always,
+    Day,
+    Night,
+}
+impl Default for SpawnTime {
+    fn default() -> Self {
+        SpawnTime::Always
+    }
+}
+impl SpawnTime {
+    pub fn is_active(&self, clock: &WorldClock) -> bool {
+        match self {
+            SpawnTime::Always => true,
+            SpawnTime::Day => !clock.is_night(),
+            SpawnTime::Night => clock.is_night(),
+        }
+    }
 }
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum ColliderType {
@@ -128,7 +155,10 @@ fn unload_mobs(
     sea_player_pos: Res<PlayerPositionUpdate>,
     mut islands: ResMut<Islands>,
 ) {
-    let island = &mut islands.0[sea_player_pos.island_id.unwrap() as usize];
+    let island = islands
+        .0
+        .get_mut(&sea_player_pos.island_id.unwrap())
+        .unwrap();
     for (entity, mob, transform) in query.iter() {
         commands.despawn_recursive(entity);
         island.mobs.push((mob.clone(), *transform));
@@ -138,12 +168,22 @@ fn unload_mobs(
 fn load_mobs(
     commands: &mut Commands,
     sea_player_pos: Res<PlayerPositionUpdate>,
+    clock: Res<WorldClock>,
     mut islands: ResMut<Islands>,
     mut collisions: ResMut<LandCollisionTree>,
 ) {
-    let island = &mut islands.0[sea_player_pos.island_id.unwrap() as usize];
+    let island = islands
+        .0
+        .get_mut(&sea_player_pos.island_id.unwrap())
+        .unwrap();
 
-    for (mob, transform) in island.mobs.drain(..) {
+    //the mobs out of their time stay on the island until the next landing
+    let (active, inactive) = island
+        .mobs
+        .drain(..)
+        .partition::<Vec<_>, _>(|(mob, _)| mob.time.is_active(&clock));
+    island.mobs = inactive;
+    for (mob, transform) in active {
         let bounding_box = mob.collider.bounding_box();
         let entity = commands //mob
             .spawn(SpriteBundle {
@@ -165,7 +205,12 @@ fn load_mobs(
 }
 
 //should use spawn tables at some point. Json config should be used for lots of things actually
-pub fn generate_mobs(mobs_config: &MobsConfig, island: &mut Island, mut hasher: SeaHasher) {
+pub fn generate_mobs(
+    mobs_config: &MobsConfig,
+    island: &mut Island,
+    biome: &str,
+    mut hasher: SeaHasher,
+) {
     "generate_mobs".to_string().hash(&mut hasher); //to shuffle things a bit between different rng gen
                                                    //iterate over the tiles and their coordinates
     let mut island_hasher = hasher;
@@ -186,24 +231,26 @@ pub fn generate_mobs(mobs_config: &MobsConfig, island: &mut Island, mut hasher:
         tile_hasher.write_usize(coord.1);
         let hash = tile_hasher.finish() % MAX_SPAWN_RATE as u64;
         let tile_kind: TileKind = tile.kind;
-        //TODO : handle different biomes
         let mut current_number = 0;
         for (material, mob_config) in mobs_config.0.iter() {
             for spawn_config in mob_config.spawn.iter() {
-                if tile_kind == spawn_config.tile_kind {
+                if tile_kind == spawn_config.tile_kind && spawn_config.biome == biome {
                     let number = (spawn_config.rate * MAX_SPAWN_RATE) as u64;
                     if hash >= current_number && hash < number + current_number {
                         let pathfinder = Some(get_pathfinding(
                             &island.tiles,
                             mob_config.pathfinding.clone(),
                         ));
+                        let id = island.mobs.len() as u32;
                         island.mobs.push((
                             Mob {
+                                id,
                                 kind: mob_config.kind.clone(),
                                 speed: mob_config.speed,
                                 material: material.clone(),
                                 pathfinder,
                                 collider: mob_config.collider.clone(),
+                                time: spawn_config.time,
                             },
                             Transform {
                                 translation: Vec3::new(
@@ -4,13 +4,17 @@ pub(crate) mod map;
 pub(crate) mod mobs;
 pub(crate) mod pathfinding;
 pub(crate) mod player;
+pub(crate) mod shipyard;
 pub(crate) mod ui;
 use bevy::prelude::*;
 use loader::LandLoaderPlugin;
 use map::LandMapPlugin;
 use player::LandPlayerPlugin;
 
-use self::{collision::LandCollisionPlugin, mobs::LandMobsPlugin, ui::LandUiPlugin};
+use self::{
+    collision::LandCollisionPlugin, mobs::LandMobsPlugin, shipyard::LandShipyardPlugin,
+    ui::LandUiPlugin,
+};
 
 pub const LAND_SCALING: f32 = 10.;
 pub struct LandPlugin;
@@ -22,6 +26,7 @@ impl Plugin for LandPlugin {
             .add_plugin(LandMapPlugin)
             .add_plugin(LandMobsPlugin)
             .add_plugin(LandCollisionPlugin)
-            .add_plugin(LandUiPlugin);
+            .add_plugin(LandUiPlugin)
+            .add_plugin(LandShipyardPlugin);
     }
 }
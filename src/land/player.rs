@@ -3,7 +3,11 @@ use std::f32::consts::PI;
 
 use crate::{
     loading::GameState,
-    sea::{loader::SeaHandles, player::PlayerPositionUpdate, ISLAND_SCALING, TILE_SIZE},
+    sea::{
+        loader::ShipsConfig,
+        player::{PlayerPositionUpdate, PlayerSave},
+        ISLAND_SCALING, TILE_SIZE,
+    },
 };
 
 use super::{
@@ -15,6 +19,8 @@ const PLAYER_DOWN: u32 = 0;
 const PLAYER_UP: u32 = 1;
 const PLAYER_RIGHT: u32 = 2;
 const PLAYER_LEFT: u32 = 3;
+//the ship of the player, waiting by the coast
+pub struct DockedShip;
 pub struct LandPlayerPlugin;
 impl Plugin for LandPlayerPlugin {
     fn build(&self, app: &mut AppBuilder) {
@@ -146,7 +152,8 @@ impl Bullet {
 fn load_system(
     commands: &mut Commands,
     handles: Res<LandHandles>,
-    sea_handles: Res<SeaHandles>,
+    ships: Res<ShipsConfig>,
+    ship_save: Res<PlayerSave>,
     sea_player_pos: Res<PlayerPositionUpdate>,
     mut camera_query: Query<&mut Transform, With<Camera>>,
     mut transition: ResMut<CameraTransition>,
@@ -208,7 +215,7 @@ fn load_system(
                 .with(UnloadLandFlag);
         })
         .spawn(SpriteSheetBundle {
-            texture_atlas: sea_handles.boat.clone(),
+            texture_atlas: ships.get(&ship_save.ship.class).0.sheet.clone(),
             transform: Transform {
                 translation: Vec3::new(boat_x, boat_y, 99.),
                 scale: Vec3::new(
@@ -228,6 +235,7 @@ fn load_system(
             },
             ..Default::default()
         })
+        .with(DockedShip)
         .with(UnloadLandFlag);
 }
 
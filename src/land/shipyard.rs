@@ -0,0 +1,77 @@
+use std::hash::Hasher;
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+use egui::Visuals;
+
+use crate::{
+    loading::GameState,
+    sea::{loader::ShipsConfig, player::PlayerSave, ships::Ship, worldgen::IslandId},
+    util::SeededHasher,
+};
+
+use super::{map::CurrentIsland, player::DockedShip};
+
+//one island in SHIPYARD_RARITY has a shipyard
+const SHIPYARD_RARITY: u64 = 4;
+
+pub struct LandShipyardPlugin;
+impl Plugin for LandShipyardPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.on_state_update(
+            GameState::STAGE,
+            GameState::Land,
+            shipyard_ui_system.system(),
+        );
+    }
+}
+
+//Whether the island has a shipyard, it only depends on the seed and the island.
+pub fn has_shipyard(mut hasher: impl Hasher, island: IslandId) -> bool {
+    hasher.write(b"shipyard");
+    hasher.write_u64(island.0);
+    hasher.finish() % SHIPYARD_RARITY == 0
+}
+
+//The shipyard trades the ship of the player for one of another class.
+fn shipyard_ui_system(
+    mut egui_context: ResMut<EguiContext>,
+    hasher: Res<SeededHasher>,
+    current_island: Res<CurrentIsland>,
+    ships: Res<ShipsConfig>,
+    mut save: ResMut<PlayerSave>,
+    mut docked_query: Query<&mut Handle<TextureAtlas>, With<DockedShip>>,
+) {
+    if !has_shipyard(hasher.get_hasher(), current_island.id) {
+        return;
+    }
+    let ctx = &mut egui_context.ctx;
+    let mut traded = None;
+    egui::Window::new("Shipyard")
+        .default_pos(egui::pos2(4.0, 80.0))
+        .resizable(false)
+        .show(ctx, |ui| {
+            *ui.visuals_mut() = Visuals::light();
+            for (_, class) in ships.0.iter() {
+                ui.horizontal(|ui| {
+                    ui.label(format!(
+                        "{}: hull {}, cargo {}, cannons {} per side",
+                        class.name, class.hull, class.cargo, class.cannons
+                    ));
+                    if ships.get(&save.ship.class).1.name == class.name {
+                        ui.label("(yours)");
+                    } else if ui.button("Trade").clicked() {
+                        traded = Some(class.name.clone());
+                    }
+                });
+            }
+        });
+    if let Some(class) = traded {
+        //the new ship comes out of the shipyard without a scratch
+        save.hull = None;
+        for mut atlas in docked_query.iter_mut() {
+            *atlas = ships.get(&class).0.sheet.clone();
+        }
+        save.ship = Ship { class };
+    }
+}
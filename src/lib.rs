@@ -0,0 +1,10 @@
+#![allow(clippy::too_many_arguments)]
+//The game is also a library, so tools can generate worlds without opening a window.
+pub mod background;
+pub mod character;
+pub mod clock;
+pub mod land;
+pub mod loading;
+pub mod menu;
+pub mod sea;
+pub mod util;
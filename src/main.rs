@@ -1,12 +1,3 @@
-#![allow(clippy::too_many_arguments)]
-mod background;
-mod character;
-mod land;
-mod loading;
-mod menu;
-mod sea;
-mod util;
-use background::SeaBackgroundPlugin;
 #[allow(unused_imports)]
 #[allow(clippy::single_component_path_imports)]
 use bevy_dylib;
@@ -18,10 +9,10 @@ use bevy::{
 };
 use bevy::{prelude::*, render::camera::OrthographicProjection};
 use bevy_egui::EguiPlugin;
-use character::CharacterPlugin;
-use land::LandPlugin;
-use menu::MenuPlugin;
-use util::SeededHasher;
+use my_bevy_game::{
+    background::SeaBackgroundPlugin, character::CharacterPlugin, clock, land::LandPlugin, loading,
+    menu::MenuPlugin, sea, util::SeededHasher,
+};
 
 pub const ZOOM: f32 = 1.;
 fn main() {
@@ -57,6 +48,7 @@ fn main() {
         .add_plugin(LandPlugin)
         .add_plugin(MenuPlugin)
         .add_plugin(CharacterPlugin)
+        .add_plugin(clock::WorldClockPlugin)
         .insert_resource(100u32)
         // Any plugin can register diagnostics
         .run();
@@ -0,0 +1,165 @@
+use serde::{Deserialize, Serialize};
+
+use super::{map::TileKind, player::CollisionType};
+
+//Every tile gets a random variant in 0..TILE_VARIANTS, rules with less variants scale it down.
+pub const TILE_VARIANTS: u32 = 8;
+
+//Matches a single tile of the 3x3 neighborhood.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum TileMatch {
+    Any,
+    Sea,
+    Sand,
+    Forest,
+    //anything that is not sea
+    Land,
+    //matches the exact kind, rocks included
+    Exact(TileKind),
+}
+impl TileMatch {
+    fn matches(&self, kind: TileKind) -> bool {
+        match (self, kind) {
+            (TileMatch::Any, _) => true,
+            (TileMatch::Sea, TileKind::Sea(_)) => true,
+            (TileMatch::Sand, TileKind::Sand(_)) => true,
+            (TileMatch::Forest, TileKind::Forest) => true,
+            (TileMatch::Land, kind) => !matches!(kind, TileKind::Sea(_)),
+            (TileMatch::Exact(expected), kind) => *expected == kind,
+            _ => false,
+        }
+    }
+}
+
+fn one() -> u32 {
+    1
+}
+
+//the sprite id is offset by one, 0 being the empty tile
+pub fn sprite_id(sprite: Option<u32>, variants: u32, variant: u32) -> u32 {
+    match sprite {
+        Some(sprite) => sprite + 1 + variant * variants.max(1) / TILE_VARIANTS,
+        None => 0,
+    }
+}
+
+//A rule maps a neighborhood to a sprite of the sheet.
+//The neighborhood is ordered as : center, N, NE, E, SE, S, SW, W, NW
+//The rule matches if any of its patterns does.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TileRule {
+    #[serde(default)]
+    pub patterns: Vec<[TileMatch; 9]>,
+    //the first sprite id in the sheet, None means nothing is drawn
+    pub sprite: Option<u32>,
+    //number of consecutive sprites in the sheet to pick from
+    #[serde(default = "one")]
+    pub variants: u32,
+    pub collision: CollisionType,
+}
+impl TileRule {
+    fn matches(&self, surroundings: &[TileKind; 9]) -> bool {
+        self.patterns.iter().any(|pattern| {
+            pattern
+                .iter()
+                .zip(surroundings.iter())
+                .all(|(m, kind)| m.matches(*kind))
+        })
+    }
+}
+
+//The terrain at a corner of a sprite, used by the constraint solver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Terrain {
+    Sea,
+    Sand,
+    Forest,
+}
+impl Terrain {
+    pub fn of(kind: TileKind) -> Self {
+        match kind {
+            TileKind::Sea(_) => Terrain::Sea,
+            TileKind::Sand(_) => Terrain::Sand,
+            TileKind::Forest => Terrain::Forest,
+        }
+    }
+
+    pub fn kind(self) -> TileKind {
+        match self {
+            Terrain::Sea => TileKind::Sea(false),
+            Terrain::Sand => TileKind::Sand(false),
+            Terrain::Forest => TileKind::Forest,
+        }
+    }
+}
+
+//A sprite described by the terrain at its corners, ordered as : NE, SE, SW, NW
+//Two sprites can be placed next to each other if the corners of their shared edge are the same.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WangTile {
+    pub corners: [Terrain; 4],
+    pub sprite: Option<u32>,
+    #[serde(default = "one")]
+    pub variants: u32,
+    pub collision: CollisionType,
+}
+impl WangTile {
+    //the terrain on most of the corners
+    pub fn main_terrain(&self) -> Terrain {
+        *self
+            .corners
+            .iter()
+            .max_by_key(|t| self.corners.iter().filter(|c| c == t).count())
+            .unwrap()
+    }
+}
+
+//The autotiling rules for a tilesheet, loaded from a ron file.
+//Rules are tried in order, so the most specific ones must come first.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TileSet {
+    pub rules: Vec<TileRule>,
+    //used when no rule matches
+    pub fallback: TileRule,
+    //the sprites available to the constraint solver
+    #[serde(default)]
+    pub wang_tiles: Vec<WangTile>,
+}
+impl Default for TileSet {
+    fn default() -> Self {
+        TileSet {
+            wang_tiles: Vec::new(),
+            rules: Vec::new(),
+            fallback: TileRule {
+                patterns: Vec::new(),
+                sprite: None,
+                variants: 1,
+                collision: CollisionType::None,
+            },
+        }
+    }
+}
+
+pub struct ResolvedTile {
+    pub sprite_id: u32,
+    pub collision: CollisionType,
+    //true if no rule matched and the fallback was used
+    pub fallback: bool,
+}
+
+impl TileSet {
+    pub fn resolve(&self, surroundings: [TileKind; 9], variant: u32) -> ResolvedTile {
+        match self.rules.iter().find(|rule| rule.matches(&surroundings)) {
+            Some(rule) => ResolvedTile {
+                sprite_id: sprite_id(rule.sprite, rule.variants, variant),
+                collision: rule.collision,
+                fallback: false,
+            },
+            None => ResolvedTile {
+                sprite_id: sprite_id(self.fallback.sprite, self.fallback.variants, variant),
+                collision: self.fallback.collision,
+                fallback: true,
+            },
+        }
+    }
+}
@@ -1,14 +1,23 @@
 use crate::loading::GameState;
-use bevy::prelude::*;
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
 use kdtree_collisions::KdValue;
-use parry2d::{math::Isometry, na::Vector2};
+use parry2d::{
+    math::{Isometry, Point, Vector},
+    na::{Unit, Vector2},
+    shape::{Ball, Shape, TriMesh},
+};
 
 use super::{
-    loader::SeaHandles,
+    loader::ShipsConfig,
     map::Islands,
-    player::{CollisionType, PlayerPositionUpdate},
-    worldgen::IslandQueue,
-    ISLAND_SCALING, TILE_SIZE,
+    player::{CollisionType, Player, PlayerPositionUpdate},
+    shape::IslandCollider,
+    ships::Ship,
+    worldgen::{Island, IslandId, IslandQueue},
+    SeaPaused, ISLAND_SCALING, TILE_SIZE,
 };
 #[derive(Debug, Default)]
 pub struct SeaCollisionTree(pub kdtree_collisions::KdTree<IslandValue, 16>);
@@ -21,7 +30,9 @@ impl Plugin for SeaCollisionPlugin {
                 GameState::Sea,
                 add_islands_system.system(),
             )
-            .add_event::<IslandSpawnEvent>()
+            .add_event::<IslandsChangedEvent>()
+            .add_event::<ShipHitIsland>()
+            .add_event::<ShipHitShip>()
             .init_resource::<SeaCollisionTree>();
     }
 }
@@ -32,7 +43,7 @@ pub struct IslandValue {
     max_x: i32,
     min_y: i32,
     max_y: i32,
-    island_id: u32,
+    pub island_id: IslandId,
 }
 impl KdValue for IslandValue {
     type Position = i32;
@@ -54,104 +65,300 @@ impl KdValue for IslandValue {
     }
 }
 
-pub struct IslandSpawnEvent(pub u32);
+//Sent when islands are added to the collision tree or removed from it, with their bounds in tiles.
+pub struct IslandsChangedEvent {
+    pub min: (i32, i32),
+    pub max: (i32, i32),
+}
+
+//the layers of the colliders, a collider is tested against the layers of its mask
+pub const LAYER_ISLAND: u32 = 1;
+pub const LAYER_SHIP: u32 = 1 << 1;
+pub const LAYER_PROJECTILE: u32 = 1 << 2;
+pub const LAYER_DEBRIS: u32 = 1 << 3;
+
+pub enum ColliderShape {
+    //radius in world units
+    Ball(f32),
+    //the collision meshes of the class of the Ship, the one used is picked by the index of the sprite
+    ShipMeshes,
+}
+
+//Anything at sea that collides, with the islands or with other colliders.
+pub struct SeaCollider {
+    pub shape: ColliderShape,
+    pub layer: u32,
+    pub mask: u32,
+}
+impl SeaCollider {
+    pub fn ship() -> Self {
+        SeaCollider {
+            shape: ColliderShape::ShipMeshes,
+            layer: LAYER_SHIP,
+            mask: LAYER_ISLAND | LAYER_SHIP | LAYER_DEBRIS,
+        }
+    }
+}
+
+//What a collider touched in the last collision pass.
+#[derive(Default)]
+pub struct SeaContacts {
+    pub island: ShipContact,
+    pub entities: Vec<Entity>,
+}
+
+//Sent when a ship hits the rigid coast of an island.
+pub struct ShipHitIsland {
+    pub entity: Entity,
+    pub island_id: IslandId,
+}
+//Sent once when two ships start touching.
+pub struct ShipHitShip(pub Entity, pub Entity);
+
+//ships are spawned in this distance from the player, in tiles
+pub const SPAWN_DISTANCE_X: i32 = 100;
+pub const SPAWN_DISTANCE_Y: i32 = 50;
+
+//What a ship touches.
+#[derive(Clone)]
+pub struct ShipContact {
+    pub collision_status: CollisionType,
+    pub island_id: Option<IslandId>,
+    pub contact: Option<(f32, f32, Unit<Vector<f32>>)>,
+    //how deep the ship is in the island, in island units
+    pub penetration: f32,
+}
+impl Default for ShipContact {
+    fn default() -> Self {
+        ShipContact {
+            collision_status: CollisionType::None,
+            island_id: None,
+            contact: None,
+            penetration: 0.,
+        }
+    }
+}
+
+fn severity(collision: CollisionType) -> u8 {
+    match collision {
+        CollisionType::None => 0,
+        CollisionType::Friction => 1,
+        CollisionType::Rigid => 2,
+    }
+}
+
+//Tests a shape at a position against the islands close to it.
+//The worst contact is kept: the rocks before the sand, then the deepest.
+pub fn ship_contact(
+    islands: &Islands,
+    kdtree: &SeaCollisionTree,
+    boat_mesh: &dyn Shape,
+    translation: Vec3,
+) -> ShipContact {
+    let mut result = ShipContact::default();
+    let x = (translation.x / TILE_SIZE as f32 / ISLAND_SCALING) as i32;
+    let y = (translation.y / TILE_SIZE as f32 / ISLAND_SCALING) as i32;
+    for close_island in kdtree.0.query_rect(x - 2, x + 2, y - 2, y + 2) {
+        let island = match islands.0.get(&close_island.island_id) {
+            Some(island) => island,
+            None => continue,
+        };
+        let island_position = Isometry::new(
+            Vector2::new(
+                (island.min_x * TILE_SIZE) as f32,
+                (island.min_y * TILE_SIZE) as f32,
+            ),
+            0.,
+        );
+        let boat_position = Isometry::new(
+            Vector2::new(
+                translation.x / ISLAND_SCALING,
+                translation.y / ISLAND_SCALING,
+            ),
+            0.,
+        );
+        let contact = |collider: &Option<IslandCollider>| {
+            let collider = collider.as_ref()?;
+            let c = parry2d::query::contact(
+                &island_position,
+                &collider.shape,
+                &boat_position,
+                boat_mesh,
+                0.,
+            )
+            .unwrap_or(None)?;
+            let x = c.point1.x - (island.min_x * TILE_SIZE) as f32;
+            let y = c.point1.y - (island.min_y * TILE_SIZE) as f32;
+            //the normal of the rectangle hit is replaced by the smoothed one of the coast
+            let normal = collider.normal_at(Point::new(x, y)).unwrap_or(c.normal1);
+            Some(((x, y, normal), (-c.dist).max(0.)))
+        };
+        let hit = contact(&island.rigid_collider)
+            .map(|hit| (CollisionType::Rigid, hit))
+            .or_else(|| {
+                contact(&island.friction_collider).map(|hit| (CollisionType::Friction, hit))
+            });
+        if let Some((collision, (intersect, penetration))) = hit {
+            let worse = severity(collision) > severity(result.collision_status)
+                || (collision == result.collision_status && penetration > result.penetration);
+            if worse {
+                result.collision_status = collision;
+                result.island_id = Some(close_island.island_id);
+                result.contact = Some(intersect);
+                result.penetration = penetration;
+            }
+        }
+    }
+    result
+}
+
+enum ColliderRef<'a> {
+    Ball(Ball),
+    Mesh(&'a TriMesh),
+}
+impl<'a> ColliderRef<'a> {
+    fn as_shape(&self) -> &dyn Shape {
+        match self {
+            ColliderRef::Ball(ball) => ball,
+            ColliderRef::Mesh(mesh) => *mesh,
+        }
+    }
+}
+
+fn collider_ref<'a>(
+    collider: &SeaCollider,
+    ship: Option<&Ship>,
+    sprite: Option<&TextureAtlasSprite>,
+    ships: &'a ShipsConfig,
+) -> Option<ColliderRef<'a>> {
+    match collider.shape {
+        ColliderShape::Ball(radius) => Some(ColliderRef::Ball(Ball::new(radius / ISLAND_SCALING))),
+        ColliderShape::ShipMeshes => ships
+            .get(&ship?.class)
+            .0
+            .meshes
+            .get(sprite?.index as usize)
+            .map(ColliderRef::Mesh),
+    }
+}
+
+//Tests every collider against the islands and the colliders of the layers in its mask.
 fn collision_system(
-    mut spawn_events: ResMut<Events<IslandSpawnEvent>>,
+    mut island_events: ResMut<Events<ShipHitIsland>>,
+    mut ship_events: ResMut<Events<ShipHitShip>>,
     mut player_pos_update: ResMut<PlayerPositionUpdate>,
-    handles: Res<SeaHandles>,
+    ships: Res<ShipsConfig>,
     islands: Res<Islands>,
     kdtree: Res<SeaCollisionTree>,
+    mut collider_query: Query<(
+        Entity,
+        &SeaCollider,
+        &Transform,
+        Option<&Ship>,
+        Option<&TextureAtlasSprite>,
+        Option<&Player>,
+        &mut SeaContacts,
+    )>,
+    paused: Res<SeaPaused>,
 ) {
-    for island_to_spawn in kdtree.0.query_rect(
-        player_pos_update.x - 100,
-        player_pos_update.x + 100,
-        player_pos_update.y - 50,
-        player_pos_update.y + 50,
-    ) {
-        spawn_events.send(IslandSpawnEvent(island_to_spawn.island_id))
-    }
-    player_pos_update.collision_status = CollisionType::None;
-    player_pos_update.island_id = None;
-    for close_island in kdtree.0.query_rect(
-        player_pos_update.x - 2,
-        player_pos_update.x + 2,
-        player_pos_update.y - 2,
-        player_pos_update.y + 2,
-    ) {
-        let island = &islands.0[close_island.island_id as usize];
-        let intersect_rigid = if let Some(rigid_mesh) = &island.rigid_trimesh {
-            parry2d::query::contact(
-                &Isometry::new(
-                    Vector2::new(
-                        (island.min_x * TILE_SIZE) as f32,
-                        (island.min_y * TILE_SIZE) as f32,
-                    ),
-                    0.,
-                ),
-                rigid_mesh,
-                &Isometry::new(
-                    Vector2::new(
-                        player_pos_update.translation.x / ISLAND_SCALING,
-                        player_pos_update.translation.y / ISLAND_SCALING,
-                    ),
-                    0.,
-                ),
-                &handles.boat_meshes[player_pos_update.sprite_id as usize],
+    if paused.0 {
+        return;
+    }
+    let colliders: Vec<_> = collider_query
+        .iter_mut()
+        .filter_map(|(entity, collider, transform, ship, sprite, _, _)| {
+            let shape = collider_ref(collider, ship, sprite, &ships)?;
+            Some((
+                entity,
+                collider.layer,
+                collider.mask,
+                shape,
+                transform.translation,
+            ))
+        })
+        .collect();
+    let ship_entities: HashSet<Entity> = colliders
+        .iter()
+        .filter(|(_, layer, ..)| layer & LAYER_SHIP != 0)
+        .map(|(entity, ..)| *entity)
+        .collect();
+    let mut results: HashMap<Entity, SeaContacts> = HashMap::default();
+    for (i, (entity, _, mask, shape, translation)) in colliders.iter().enumerate() {
+        let mut contacts = SeaContacts::default();
+        if mask & LAYER_ISLAND != 0 {
+            contacts.island = ship_contact(&islands, &kdtree, shape.as_shape(), *translation);
+        }
+        let position = Isometry::translation(
+            translation.x / ISLAND_SCALING,
+            translation.y / ISLAND_SCALING,
+        );
+        for (j, (other, other_layer, _, other_shape, other_translation)) in
+            colliders.iter().enumerate()
+        {
+            if i == j || mask & other_layer == 0 {
+                continue;
+            }
+            let other_position = Isometry::translation(
+                other_translation.x / ISLAND_SCALING,
+                other_translation.y / ISLAND_SCALING,
+            );
+            let touching = parry2d::query::contact(
+                &position,
+                shape.as_shape(),
+                &other_position,
+                other_shape.as_shape(),
                 0.,
             )
             .unwrap_or(None)
-            .map(|c| {
-                (
-                    c.point1.x - (island.min_x * TILE_SIZE) as f32,
-                    c.point1.y - (island.min_y * TILE_SIZE) as f32,
-                    c.normal1,
-                )
-            })
-        } else {
-            None
-        };
-        if intersect_rigid.is_some() {
-            player_pos_update.collision_status = CollisionType::Rigid;
-            player_pos_update.island_id = Some(close_island.island_id);
-            player_pos_update.contact = intersect_rigid;
-        } else {
-            let intersect_friction = if let Some(friction_mesh) = &island.friction_trimesh {
-                parry2d::query::contact(
-                    &Isometry::new(
-                        Vector2::new(
-                            (island.min_x * TILE_SIZE) as f32,
-                            (island.min_y * TILE_SIZE) as f32,
-                        ),
-                        0.,
-                    ),
-                    friction_mesh,
-                    &Isometry::new(
-                        Vector2::new(
-                            player_pos_update.translation.x / ISLAND_SCALING,
-                            player_pos_update.translation.y / ISLAND_SCALING,
-                        ),
-                        0.,
-                    ),
-                    &handles.boat_meshes[player_pos_update.sprite_id as usize],
-                    0.,
-                )
-                .unwrap_or(None)
-                .map(|c| {
-                    (
-                        c.point1.x - (island.min_x * TILE_SIZE) as f32,
-                        c.point1.y - (island.min_y * TILE_SIZE) as f32,
-                        c.normal1,
-                    )
-                })
-            } else {
-                None
-            };
-            if intersect_friction.is_some() {
-                player_pos_update.collision_status = CollisionType::Friction;
-                player_pos_update.island_id = Some(close_island.island_id);
-                player_pos_update.contact = intersect_friction;
+            .is_some();
+            if touching {
+                contacts.entities.push(*other);
+            }
+        }
+        results.insert(*entity, contacts);
+    }
+    for (entity, collider, _, _, _, player, mut contacts) in collider_query.iter_mut() {
+        let new_contacts = results.remove(&entity).unwrap_or_default();
+        if collider.layer & LAYER_SHIP != 0 {
+            if let (CollisionType::Rigid, Some(island_id)) = (
+                new_contacts.island.collision_status,
+                new_contacts.island.island_id,
+            ) {
+                if contacts.island.collision_status != CollisionType::Rigid {
+                    island_events.send(ShipHitIsland { entity, island_id });
+                }
+            }
+            //each pair is sent once
+            for other in new_contacts.entities.iter() {
+                if entity.id() < other.id()
+                    && ship_entities.contains(other)
+                    && !contacts.entities.contains(other)
+                {
+                    ship_events.send(ShipHitShip(entity, *other));
+                }
             }
         }
+        if player.is_some() {
+            let contact = &new_contacts.island;
+            player_pos_update.collision_status = contact.collision_status;
+            player_pos_update.island_id = contact.island_id;
+            //the last contact is kept, it is where the player lands
+            if contact.contact.is_some() {
+                player_pos_update.contact = contact.contact;
+                player_pos_update.penetration = contact.penetration;
+            }
+        }
+        *contacts = new_contacts;
+    }
+}
+
+pub(crate) fn island_value(island: &Island) -> IslandValue {
+    IslandValue {
+        min_x: island.min_x,
+        max_x: island.max_x,
+        min_y: island.min_y,
+        max_y: island.max_y,
+        island_id: island.id,
     }
 }
 
@@ -159,17 +366,17 @@ fn add_islands_system(
     mut islands_to_add: ResMut<IslandQueue>,
     mut islands: ResMut<Islands>,
     mut kdtree: ResMut<SeaCollisionTree>,
+    mut changed_events: ResMut<Events<IslandsChangedEvent>>,
 ) {
     for island in islands_to_add.0.drain(..) {
-        let island_id = islands.0.len() as u32;
-        let island_value = IslandValue {
-            min_x: island.min_x,
-            max_x: island.max_x,
-            min_y: island.min_y,
-            max_y: island.max_y,
-            island_id,
-        };
-        kdtree.0.insert(island_value);
-        islands.0.push(island);
+        if islands.0.contains_key(&island.id) {
+            continue;
+        }
+        kdtree.0.insert(island_value(&island));
+        changed_events.send(IslandsChangedEvent {
+            min: (island.min_x, island.min_y),
+            max: (island.max_x, island.max_y),
+        });
+        islands.0.insert(island.id, island);
     }
 }
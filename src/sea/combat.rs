@@ -0,0 +1,420 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+
+use crate::loading::GameState;
+
+use super::{
+    collision::{
+        ColliderShape, SeaCollider, SeaContacts, ShipHitShip, LAYER_DEBRIS, LAYER_ISLAND,
+        LAYER_PROJECTILE, LAYER_SHIP,
+    },
+    currents::Currents,
+    loader::ShipsConfig,
+    player::{CollisionType, Player},
+    sailing::ShipMotion,
+    ships::{Hull, Ship, ShipClass},
+    SeaPaused,
+};
+
+//speed of a cannonball, in world units per second, on top of the speed of the ship firing it
+const CANNONBALL_SPEED: f32 = 300.;
+//how long a cannonball flies before falling into the sea, in seconds
+const CANNONBALL_FLIGHT_TIME: f32 = 1.2;
+const CANNONBALL_RADIUS: f32 = 2.;
+const CANNONBALL_DAMAGE: f32 = 12.;
+//distance between two cannons of a side, along the hull
+const CANNON_SPACING: f32 = 12.;
+//distance from the middle of the ship to its sides
+const CANNON_SIDE_OFFSET: f32 = 20.;
+const SPLASH_TIME: f32 = 0.6;
+//pieces of wood left floating by a sunk ship
+const DEBRIS_PIECES: u32 = 5;
+const DEBRIS_RADIUS: f32 = 3.;
+const DEBRIS_SPEED: f32 = 40.;
+const DEBRIS_FRICTION: f32 = 1.;
+//how long the debris float before sinking, in seconds
+const DEBRIS_TIME: f32 = 20.;
+//speed given to the debris by a ship going through them
+const DEBRIS_PUSH: f32 = 60.;
+//ships hitting each other slower than that aren't harmed
+const RAMMING_SPEED_THRESHOLD: f32 = 40.;
+//hull points lost per unit of speed above the threshold
+const RAMMING_DAMAGE: f32 = 0.2;
+
+pub struct SeaCombatPlugin;
+impl Plugin for SeaCombatPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<CombatMaterials>()
+            .on_state_update(GameState::STAGE, GameState::Sea, fire_system.system())
+            .on_state_update(GameState::STAGE, GameState::Sea, reload_system.system())
+            .on_state_update(GameState::STAGE, GameState::Sea, cannonball_system.system())
+            .on_state_update(GameState::STAGE, GameState::Sea, splash_system.system())
+            .on_state_update(GameState::STAGE, GameState::Sea, debris_system.system())
+            .on_state_update(GameState::STAGE, GameState::Sea, ramming_system.system())
+            .on_state_exit(GameState::STAGE, GameState::Sea, unload_system.system());
+    }
+}
+
+pub struct CombatMaterials {
+    cannonball: Handle<ColorMaterial>,
+    splash: Handle<ColorMaterial>,
+    debris: Handle<ColorMaterial>,
+}
+impl FromResources for CombatMaterials {
+    fn from_resources(resources: &Resources) -> Self {
+        let mut materials = resources.get_mut::<Assets<ColorMaterial>>().unwrap();
+        CombatMaterials {
+            cannonball: materials.add(Color::rgb(0.15, 0.15, 0.15).into()),
+            splash: materials.add(Color::rgba(0.85, 0.95, 1., 0.8).into()),
+            debris: materials.add(Color::rgb(0.45, 0.3, 0.15).into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Side {
+    Port,
+    Starboard,
+}
+impl Side {
+    //the direction the cannons of that side fire to, for a ship with that heading
+    pub fn direction(self, heading: Vec2) -> Vec2 {
+        match self {
+            Side::Port => Vec2::new(-heading.y, heading.x),
+            Side::Starboard => Vec2::new(heading.y, -heading.x),
+        }
+    }
+}
+
+//The cannons of a ship, each side reloads on its own.
+pub struct Cannons {
+    port: Timer,
+    starboard: Timer,
+}
+impl Cannons {
+    //the cannons start loaded
+    pub fn new(class: &ShipClass) -> Self {
+        let mut loaded = Timer::from_seconds(class.reload, false);
+        loaded.tick(class.reload);
+        Cannons {
+            port: loaded.clone(),
+            starboard: loaded,
+        }
+    }
+
+    fn timer(&mut self, side: Side) -> &mut Timer {
+        match side {
+            Side::Port => &mut self.port,
+            Side::Starboard => &mut self.starboard,
+        }
+    }
+
+    pub fn is_loaded(&self, side: Side) -> bool {
+        match side {
+            Side::Port => self.port.finished(),
+            Side::Starboard => self.starboard.finished(),
+        }
+    }
+}
+
+pub struct Cannonball {
+    velocity: Vec2,
+    timer: Timer,
+    //a ship can't hit itself
+    shooter: Entity,
+}
+
+struct Splash {
+    timer: Timer,
+}
+
+//Floating wood, carried by the currents until it washes ashore.
+struct Debris {
+    velocity: Vec2,
+    timer: Timer,
+}
+
+//Fires all the cannons of a side if they are loaded, they fire along the hull.
+pub fn fire_broadside(
+    commands: &mut Commands,
+    materials: &CombatMaterials,
+    shooter: Entity,
+    position: Vec2,
+    heading: Vec2,
+    velocity: Vec2,
+    class: &ShipClass,
+    cannons: &mut Cannons,
+    side: Side,
+) {
+    if !cannons.is_loaded(side) {
+        return;
+    }
+    cannons.timer(side).reset();
+    let direction = side.direction(heading);
+    for i in 0..class.cannons {
+        let along = (i as f32 - (class.cannons as f32 - 1.) / 2.) * CANNON_SPACING;
+        let start = position + heading * along + direction * CANNON_SIDE_OFFSET;
+        commands
+            .spawn(SpriteBundle {
+                material: materials.cannonball.clone(),
+                sprite: Sprite::new(Vec2::splat(CANNONBALL_RADIUS * 2.)),
+                transform: Transform::from_translation(start.extend(101.)),
+                ..Default::default()
+            })
+            .with(Cannonball {
+                velocity: velocity + direction * CANNONBALL_SPEED,
+                timer: Timer::from_seconds(CANNONBALL_FLIGHT_TIME, false),
+                shooter,
+            })
+            .with(SeaCollider {
+                shape: ColliderShape::Ball(CANNONBALL_RADIUS),
+                layer: LAYER_PROJECTILE,
+                mask: LAYER_ISLAND | LAYER_SHIP,
+            })
+            .with(SeaContacts::default());
+    }
+}
+
+//Q fires to port, E to starboard
+fn fire_system(
+    commands: &mut Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    materials: Res<CombatMaterials>,
+    ships: Res<ShipsConfig>,
+    mut player_query: Query<
+        (Entity, &ShipMotion, &Ship, &Hull, &Transform, &mut Cannons),
+        With<Player>,
+    >,
+    paused: Res<SeaPaused>,
+) {
+    if paused.0 {
+        return;
+    }
+    for (entity, player, ship, hull, transform, mut cannons) in player_query.iter_mut() {
+        if hull.points <= 0. {
+            continue;
+        }
+        let side = if keyboard_input.just_pressed(KeyCode::Q) {
+            Side::Port
+        } else if keyboard_input.just_pressed(KeyCode::E) {
+            Side::Starboard
+        } else {
+            continue;
+        };
+        fire_broadside(
+            commands,
+            &materials,
+            entity,
+            transform.translation.truncate(),
+            player.heading(),
+            player.velocity(),
+            &ships.get(&ship.class).1,
+            &mut cannons,
+            side,
+        );
+    }
+}
+
+fn reload_system(time: Res<Time>, mut cannons_query: Query<&mut Cannons>, paused: Res<SeaPaused>) {
+    if paused.0 {
+        return;
+    }
+    for mut cannons in cannons_query.iter_mut() {
+        cannons.port.tick(time.delta_seconds());
+        cannons.starboard.tick(time.delta_seconds());
+    }
+}
+
+//Moves the cannonballs, they hit the ships and the coast they touch, or fall in the sea at the end of their flight.
+fn cannonball_system(
+    commands: &mut Commands,
+    time: Res<Time>,
+    materials: Res<CombatMaterials>,
+    mut cannonball_query: Query<(Entity, &mut Cannonball, &SeaContacts, &mut Transform)>,
+    mut hull_query: Query<&mut Hull>,
+    paused: Res<SeaPaused>,
+) {
+    if paused.0 {
+        return;
+    }
+    for (entity, mut cannonball, contacts, mut transform) in cannonball_query.iter_mut() {
+        let mut hit = false;
+        for other in contacts.entities.iter() {
+            if *other == cannonball.shooter {
+                continue;
+            }
+            if let Ok(mut hull) = hull_query.get_mut(*other) {
+                hull.damage(CANNONBALL_DAMAGE);
+                hit = true;
+                break;
+            }
+        }
+        let ashore = contacts.island.collision_status == CollisionType::Rigid;
+        cannonball.timer.tick(time.delta_seconds());
+        let position = transform.translation.truncate();
+        if hit || ashore || cannonball.timer.finished() {
+            commands.despawn(entity);
+            spawn_splash(commands, &materials, position, hit);
+            continue;
+        }
+        transform.translation += (cannonball.velocity * time.delta_seconds()).extend(0.);
+    }
+}
+
+//Ships hitting each other are harmed by the speed of the impact.
+fn ramming_system(
+    ships: Res<ShipsConfig>,
+    mut hit_reader: EventReader<ShipHitShip>,
+    mut ship_query: Query<(&ShipMotion, &Ship, &mut Hull)>,
+    paused: Res<SeaPaused>,
+) {
+    if paused.0 {
+        return;
+    }
+    for ShipHitShip(a, b) in hit_reader.iter() {
+        let velocity = |entity: Entity| {
+            ship_query
+                .get_component::<ShipMotion>(entity)
+                .map(|motion| motion.velocity())
+                .ok()
+        };
+        let speed = match (velocity(*a), velocity(*b)) {
+            (Some(a), Some(b)) => (a - b).length(),
+            _ => continue,
+        };
+        let damage = (speed - RAMMING_SPEED_THRESHOLD).max(0.) * RAMMING_DAMAGE;
+        for entity in [*a, *b].iter() {
+            if let Ok((_, ship, mut hull)) = ship_query.get_mut(*entity) {
+                //the bigger ship suffers less
+                hull.damage(damage * 100. / ships.get(&ship.class).1.hull as f32);
+            }
+        }
+    }
+}
+
+//Water splashing, or wood flying when something is hit.
+pub fn spawn_splash(
+    commands: &mut Commands,
+    materials: &CombatMaterials,
+    position: Vec2,
+    debris: bool,
+) {
+    let material = if debris {
+        materials.debris.clone()
+    } else {
+        materials.splash.clone()
+    };
+    commands
+        .spawn(SpriteBundle {
+            material,
+            sprite: Sprite::new(Vec2::splat(CANNONBALL_RADIUS * 4.)),
+            transform: Transform::from_translation(position.extend(101.)),
+            ..Default::default()
+        })
+        .with(Splash {
+            timer: Timer::from_seconds(SPLASH_TIME, false),
+        });
+}
+
+//Scatters pieces of wood where a ship sank.
+pub fn spawn_debris(commands: &mut Commands, materials: &CombatMaterials, position: Vec2) {
+    for i in 0..DEBRIS_PIECES {
+        let angle = i as f32 / DEBRIS_PIECES as f32 * 2. * PI;
+        let direction = Vec2::new(angle.cos(), angle.sin());
+        commands
+            .spawn(SpriteBundle {
+                material: materials.debris.clone(),
+                sprite: Sprite::new(Vec2::new(DEBRIS_RADIUS * 3., DEBRIS_RADIUS * 1.5)),
+                transform: Transform::from_translation(
+                    (position + direction * DEBRIS_RADIUS * 2.).extend(98.),
+                ),
+                ..Default::default()
+            })
+            .with(Debris {
+                velocity: direction * DEBRIS_SPEED,
+                timer: Timer::from_seconds(DEBRIS_TIME, false),
+            })
+            .with(SeaCollider {
+                shape: ColliderShape::Ball(DEBRIS_RADIUS),
+                layer: LAYER_DEBRIS,
+                mask: LAYER_ISLAND | LAYER_SHIP,
+            })
+            .with(SeaContacts::default());
+    }
+}
+
+//The debris drift with the currents and are pushed by the ships, they stop on the coast and sink after a while.
+fn debris_system(
+    commands: &mut Commands,
+    time: Res<Time>,
+    currents: Res<Currents>,
+    mut debris_query: Query<(Entity, &mut Debris, &SeaContacts, &mut Transform)>,
+    ship_query: Query<&Transform, (With<Ship>, Without<Debris>)>,
+    paused: Res<SeaPaused>,
+) {
+    if paused.0 {
+        return;
+    }
+    for (entity, mut debris, contacts, mut transform) in debris_query.iter_mut() {
+        debris.timer.tick(time.delta_seconds());
+        if debris.timer.finished() {
+            commands.despawn(entity);
+            continue;
+        }
+        let position = transform.translation.truncate();
+        for ship_transform in contacts
+            .entities
+            .iter()
+            .filter_map(|other| ship_query.get(*other).ok())
+        {
+            let away = position - ship_transform.translation.truncate();
+            if away.length() > f32::EPSILON {
+                debris.velocity = away.normalize() * DEBRIS_PUSH;
+            }
+        }
+        if contacts.island.collision_status != CollisionType::None {
+            debris.velocity = Vec2::zero();
+            continue;
+        }
+        let drift = debris.velocity + currents.sample(position);
+        transform.translation += (drift * time.delta_seconds()).extend(0.);
+        let velocity = debris.velocity;
+        debris.velocity -= velocity * (DEBRIS_FRICTION * time.delta_seconds()).min(1.);
+    }
+}
+
+//the splashes grow, then vanish
+fn splash_system(
+    commands: &mut Commands,
+    time: Res<Time>,
+    mut splash_query: Query<(Entity, &mut Splash, &mut Transform)>,
+    paused: Res<SeaPaused>,
+) {
+    if paused.0 {
+        return;
+    }
+    for (entity, mut splash, mut transform) in splash_query.iter_mut() {
+        splash.timer.tick(time.delta_seconds());
+        let scale = 0.5 + splash.timer.percent();
+        transform.scale = Vec3::new(scale, scale, 1.);
+        if splash.timer.finished() {
+            commands.despawn(entity);
+        }
+    }
+}
+
+fn unload_system(
+    commands: &mut Commands,
+    cannonball_query: Query<Entity, With<Cannonball>>,
+    splash_query: Query<Entity, With<Splash>>,
+    debris_query: Query<Entity, With<Debris>>,
+) {
+    for entity in cannonball_query
+        .iter()
+        .chain(splash_query.iter())
+        .chain(debris_query.iter())
+    {
+        commands.despawn(entity);
+    }
+}
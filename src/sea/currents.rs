@@ -0,0 +1,181 @@
+use std::{hash::Hasher, sync::Arc};
+
+use bevy::prelude::*;
+use noise::{NoiseFn, Perlin, Seedable};
+
+use crate::{loading::GameState, util::SeededHasher};
+
+use super::{
+    player::Player,
+    worldgen::{IslandGenerator, WorldGenerator},
+    ISLAND_SCALING, TILE_SIZE,
+};
+
+//size of the current patterns, in world units
+const CURRENT_SCALE: f64 = 1. / 3000.;
+//speed of the currents, in world units per second
+const CURRENT_SPEED: f32 = 12.;
+//a channel is sea with land on both sides closer than that, in tiles
+const CHANNEL_WIDTH: i32 = 8;
+//how much faster the currents are in a channel
+const CHANNEL_BOOST: f32 = 2.5;
+
+//the debug arrows are on a grid around the player
+const ARROWS_PER_SIDE: i32 = 9;
+const ARROW_SPACING: f32 = 128.;
+const ARROW_LENGTH: f32 = 4.;
+
+pub struct SeaCurrentsPlugin;
+impl Plugin for SeaCurrentsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Currents>()
+            .init_resource::<CurrentsDebug>()
+            .on_state_update(GameState::STAGE, GameState::Sea, setup.system())
+            .on_state_exit(GameState::STAGE, GameState::Sea, despawn_arrows.system())
+            .on_state_update(GameState::STAGE, GameState::Sea, debug_system.system());
+    }
+}
+
+//The currents of the sea, a flow that doesn't depend on time.
+//It is the curl of a noise, so the water neither piles up nor vanishes anywhere.
+#[derive(Default)]
+pub struct Currents {
+    noise: Perlin,
+    //to find the channels between islands
+    generator: Option<Arc<IslandGenerator>>,
+}
+impl Currents {
+    pub fn new(mut hasher: impl Hasher, generator: Arc<IslandGenerator>) -> Self {
+        hasher.write(b"currents");
+        Currents {
+            noise: Perlin::new().set_seed(hasher.finish() as u32),
+            generator: Some(generator),
+        }
+    }
+
+    //The velocity of the water at a world position, in world units per second.
+    pub fn sample(&self, position: Vec2) -> Vec2 {
+        const EPSILON: f64 = 0.01;
+        let x = position.x as f64 * CURRENT_SCALE;
+        let y = position.y as f64 * CURRENT_SCALE;
+        let potential = |x: f64, y: f64| self.noise.get([x, y]);
+        let flow = Vec2::new(
+            ((potential(x, y + EPSILON) - potential(x, y - EPSILON)) / (2. * EPSILON)) as f32,
+            ((potential(x - EPSILON, y) - potential(x + EPSILON, y)) / (2. * EPSILON)) as f32,
+        ) * CURRENT_SPEED;
+        if self.in_channel(position, flow) {
+            flow * CHANNEL_BOOST
+        } else {
+            flow
+        }
+    }
+
+    //Looks for land on both sides of the flow.
+    fn in_channel(&self, position: Vec2, flow: Vec2) -> bool {
+        let generator = match &self.generator {
+            Some(generator) => generator,
+            None => return false,
+        };
+        if flow.length_squared() <= f32::EPSILON {
+            return false;
+        }
+        let tile_size = TILE_SIZE as f32 * ISLAND_SCALING;
+        let tile = position / tile_size;
+        let side = Vec2::new(-flow.y, flow.x).normalize();
+        let land_towards = |side: Vec2| {
+            (1..=CHANNEL_WIDTH).any(|distance| {
+                let point = tile + side * distance as f32;
+                generator.is_land((point.x.floor() as i32, point.y.floor() as i32))
+            })
+        };
+        land_towards(side) && land_towards(-side)
+    }
+}
+
+#[derive(Default)]
+struct CurrentsDebug {
+    shown: bool,
+}
+struct CurrentArrow;
+
+//the currents follow the land, they wait for the generator of the world
+fn setup(
+    hasher: Res<SeededHasher>,
+    generator: Res<WorldGenerator>,
+    mut currents: ResMut<Currents>,
+) {
+    if currents.generator.is_some() {
+        return;
+    }
+    if let Some(generator) = &generator.0 {
+        *currents = Currents::new(hasher.get_hasher(), generator.clone());
+    }
+}
+
+//F3 shows the currents around the player
+fn debug_system(
+    commands: &mut Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    currents: Res<Currents>,
+    mut debug: ResMut<CurrentsDebug>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut material: Local<Option<Handle<ColorMaterial>>>,
+    player_query: Query<&Transform, With<Player>>,
+    mut arrow_query: Query<(Entity, &mut Transform), With<CurrentArrow>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F3) {
+        debug.shown = !debug.shown;
+        if !debug.shown {
+            for (entity, _) in arrow_query.iter_mut() {
+                commands.despawn_recursive(entity);
+            }
+            return;
+        }
+        let material = material
+            .get_or_insert_with(|| materials.add(Color::rgba(1., 1., 1., 0.7).into()))
+            .clone();
+        for _ in 0..ARROWS_PER_SIDE * ARROWS_PER_SIDE {
+            commands
+                .spawn(SpriteBundle {
+                    material: material.clone(),
+                    sprite: Sprite::new(Vec2::new(1., 3.)),
+                    ..Default::default()
+                })
+                .with(CurrentArrow);
+        }
+    }
+    if !debug.shown {
+        return;
+    }
+    for player_transform in player_query.iter() {
+        let origin = (player_transform.translation.truncate() / ARROW_SPACING).floor()
+            - Vec2::splat((ARROWS_PER_SIDE / 2) as f32);
+        for (i, (_, mut transform)) in arrow_query.iter_mut().enumerate() {
+            let i = i as i32;
+            let cell =
+                origin + Vec2::new((i % ARROWS_PER_SIDE) as f32, (i / ARROWS_PER_SIDE) as f32);
+            let position = cell * ARROW_SPACING;
+            let flow = currents.sample(position);
+            //the sprite is a line starting at the sampled point
+            let length = flow.length() * ARROW_LENGTH;
+            let angle = flow.y.atan2(flow.x);
+            let middle = position + Vec2::new(angle.cos(), angle.sin()) * length / 2.;
+            *transform = Transform {
+                translation: middle.extend(50.),
+                rotation: Quat::from_rotation_z(angle),
+                scale: Vec3::new(length.max(1.), 1., 1.),
+            };
+        }
+    }
+}
+
+fn despawn_arrows(
+    commands: &mut Commands,
+    mut debug: ResMut<CurrentsDebug>,
+    arrow_query: Query<Entity, With<CurrentArrow>>,
+) {
+    debug.shown = false;
+    for entity in arrow_query.iter() {
+        commands.despawn_recursive(entity);
+    }
+}
@@ -0,0 +1,132 @@
+use bevy::{prelude::*, utils::HashMap};
+use serde::{Deserialize, Serialize};
+
+use crate::{clock::WorldClock, loading::GameState};
+
+use super::{
+    collision::{IslandsChangedEvent, SeaCollisionTree},
+    map::{Islands, TileKind},
+    player::PlayerPositionUpdate,
+    weather::CurrentWeather,
+    worldgen::IslandId,
+    SeaPaused,
+};
+
+//tiles revealed around the player in full daylight
+pub const SIGHT_RADIUS: i32 = 24;
+//the fog is stored by square chunks of that many tiles, one bit per tile
+const FOG_CHUNK_SIZE: i32 = 32;
+
+pub struct SeaExplorationPlugin;
+impl Plugin for SeaExplorationPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Exploration>().on_state_update(
+            GameState::STAGE,
+            GameState::Sea,
+            exploration_system.system(),
+        );
+    }
+}
+
+//An island as drawn on the map, kept when it is dropped from memory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChartedIsland {
+    pub min_x: i32,
+    pub min_y: i32,
+    //indexed like Island.tiles
+    pub tiles: Vec<Vec<TileKind>>,
+}
+
+//The fog of war: the tiles the player has seen, and the islands found there.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Exploration {
+    chunks: HashMap<(i32, i32), Vec<u32>>,
+    pub islands: HashMap<IslandId, ChartedIsland>,
+}
+
+impl Exploration {
+    //the rows missing from a save are in the fog
+    pub fn is_revealed(&self, (x, y): (i32, i32)) -> bool {
+        let chunk = (x.div_euclid(FOG_CHUNK_SIZE), y.div_euclid(FOG_CHUNK_SIZE));
+        self.chunks
+            .get(&chunk)
+            .and_then(|rows| rows.get(y.rem_euclid(FOG_CHUNK_SIZE) as usize))
+            .map(|row| row & (1 << x.rem_euclid(FOG_CHUNK_SIZE)) != 0)
+            .unwrap_or(false)
+    }
+
+    fn reveal(&mut self, (x, y): (i32, i32)) {
+        let chunk = (x.div_euclid(FOG_CHUNK_SIZE), y.div_euclid(FOG_CHUNK_SIZE));
+        let rows = self
+            .chunks
+            .entry(chunk)
+            .or_insert_with(|| vec![0; FOG_CHUNK_SIZE as usize]);
+        if rows.len() < FOG_CHUNK_SIZE as usize {
+            rows.resize(FOG_CHUNK_SIZE as usize, 0);
+        }
+        rows[y.rem_euclid(FOG_CHUNK_SIZE) as usize] |= 1 << x.rem_euclid(FOG_CHUNK_SIZE);
+    }
+
+    //reveals the tiles in a radius around a tile
+    pub fn reveal_around(&mut self, (x, y): (i32, i32), radius: i32) {
+        for dx in -radius..=radius {
+            for dy in -radius..=radius {
+                if dx * dx + dy * dy <= radius * radius {
+                    self.reveal((x + dx, y + dy));
+                }
+            }
+        }
+    }
+}
+
+//Reveals the tiles around the player when it moves, and charts the islands in sight
+//when it moves or when new islands are generated.
+fn exploration_system(
+    pos_update: Res<PlayerPositionUpdate>,
+    islands: Res<Islands>,
+    kdtree: Res<SeaCollisionTree>,
+    clock: Res<WorldClock>,
+    weather: Res<CurrentWeather>,
+    mut exploration: ResMut<Exploration>,
+    mut last_tile: Local<Option<(i32, i32)>>,
+    mut changed_reader: EventReader<IslandsChangedEvent>,
+    paused: Res<SeaPaused>,
+) {
+    if paused.0 {
+        return;
+    }
+    let tile = (pos_update.x, pos_update.y);
+    //the player sees less far at night and in the fog
+    let radius = (SIGHT_RADIUS as f32 * clock.visibility() * weather.sight).round() as i32;
+    let moved = *last_tile != Some(tile);
+    let changed = changed_reader.iter().count() > 0;
+    if moved {
+        *last_tile = Some(tile);
+        exploration.reveal_around(tile, radius);
+    }
+    if !moved && !changed {
+        return;
+    }
+    for value in kdtree.0.query_rect(
+        tile.0 - radius,
+        tile.0 + radius,
+        tile.1 - radius,
+        tile.1 + radius,
+    ) {
+        if exploration.islands.contains_key(&value.island_id) {
+            continue;
+        }
+        if let Some(island) = islands.0.get(&value.island_id) {
+            let charted = ChartedIsland {
+                min_x: island.min_x,
+                min_y: island.min_y,
+                tiles: island
+                    .tiles
+                    .iter()
+                    .map(|column| column.iter().map(|tile| tile.kind).collect())
+                    .collect(),
+            };
+            exploration.islands.insert(island.id, charted);
+        }
+    }
+}
@@ -6,58 +6,113 @@ use parry2d::shape::TriMesh;
 
 use crate::{loading::GameState, util::texture_atlas_to_trimeshes};
 
-use super::{player::PlayerPositionUpdate, worldgen::Biome, ISLAND_SCALING, TILE_SIZE};
+use super::{
+    autotile::TileSet,
+    player::PlayerPositionUpdate,
+    ships::ShipClass,
+    worldgen::{Biome, SheetConfig},
+    SeaPaused, ISLAND_SCALING,
+};
 
 #[derive(Default)]
 pub struct SeaHandles {
     pub sea_pipeline: Handle<PipelineDescriptor>,
     pub sea_sheet: Handle<TextureAtlas>,
-    pub islands_sheet: Handle<TextureAtlas>,
-    pub boat: Handle<TextureAtlas>,
-    pub boat_collisions: Handle<TextureAtlas>,
-    pub boat_meshes: Vec<TriMesh>,
-    pub islands_material: Handle<ColorMaterial>,
 }
 
+//the sprites of a biome, for the islands seen from the sea and for the land view
 #[derive(Clone, Default)]
-pub struct BiomeConfig(pub Arc<Vec<(Handle<TextureAtlas>, Biome)>>);
+pub struct BiomeAssets {
+    pub sea_sheet: Handle<TextureAtlas>,
+    pub sea_material: Handle<ColorMaterial>,
+    pub land_sheet: Handle<TextureAtlas>,
+    pub land_material: Handle<ColorMaterial>,
+}
+
+#[derive(Clone, Default)]
+pub struct BiomeConfig(pub Arc<Vec<(BiomeAssets, Biome)>>);
+
+//the sprites of a ship class, and the collision shape of each of them once the sheet is loaded
+#[derive(Clone, Default)]
+pub struct ShipAssets {
+    pub sheet: Handle<TextureAtlas>,
+    pub collisions: Handle<TextureAtlas>,
+    pub meshes: Vec<TriMesh>,
+}
+
+#[derive(Clone, Default)]
+pub struct ShipsConfig(pub Arc<Vec<(ShipAssets, ShipClass)>>);
+impl ShipsConfig {
+    //The class with that name, the first one if it is no longer in the config.
+    pub fn get(&self, name: &str) -> &(ShipAssets, ShipClass) {
+        self.0
+            .iter()
+            .find(|(_, class)| class.name == name)
+            .unwrap_or(&self.0[0])
+    }
+}
 pub struct SeaLoaderPlugin;
 impl Plugin for SeaLoaderPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        let worldgen_config = {
-            // let asset_server = app
-            //     .resources()
-            //     .get::<AssetServer>()
-            //     .expect("Asset server not found");
-            // let mut atlases = app
-            //     .resources()
-            //     .get_mut::<Assets<TextureAtlas>>()
-            //     .expect("Asset server not found");
-            BiomeConfig(Arc::new(
-                read_worldgen_config()
-                    .drain(..)
-                    .map(|worldgen_config| {
-                        // let texture_handle =
-                        //     asset_server.load(std::path::Path::new(&worldgen_config.sea_sheet));
-                        // let atlas =
-                        //     TextureAtlas::from_grid(texture_handle, Vec2::new(16., 16.), 4, 47);
-                        (Handle::default(), worldgen_config)
-                    })
-                    .collect(),
-            ))
-        };
         app.add_system(enter_island_system.system())
             .add_system(on_loaded.system())
             .add_startup_system(setup.system())
             .init_resource::<SeaHandles>()
-            .insert_resource(worldgen_config);
+            .init_resource::<BiomeConfig>()
+            .init_resource::<ShipsConfig>();
     }
 }
 
-fn read_worldgen_config() -> Vec<Biome> {
+//Also usable without the app, the biomes come with their tileset loaded.
+pub fn read_worldgen_config() -> Vec<Biome> {
     let worldgen_config_string =
         std::fs::read_to_string("config/worldgen.ron").expect("worldgen config file not found");
-    ron::from_str(&worldgen_config_string).expect("syntax error on worldgen config file")
+    let mut biomes: Vec<Biome> =
+        ron::from_str(&worldgen_config_string).expect("syntax error on worldgen config file");
+    for biome in biomes.iter_mut() {
+        biome.tile_set = Arc::new(read_tileset(&biome.tileset));
+    }
+    biomes
+}
+
+fn read_tileset(path: &str) -> TileSet {
+    let tileset_string = std::fs::read_to_string(path).expect("tileset file not found");
+    ron::from_str(&tileset_string).expect("syntax error on tileset file")
+}
+
+pub fn read_ships_config() -> Vec<ShipClass> {
+    let ships_config_string =
+        std::fs::read_to_string("config/ships.ron").expect("ships config file not found");
+    let classes: Vec<ShipClass> =
+        ron::from_str(&ships_config_string).expect("syntax error on ships config file");
+    //the player starts with the first class
+    assert!(
+        !classes.is_empty(),
+        "no ship class in the ships config file"
+    );
+    classes
+}
+
+fn sheet_atlas(sheet: &SheetConfig, asset_server: &AssetServer) -> TextureAtlas {
+    let texture_handle = asset_server.load(std::path::Path::new(&sheet.path));
+    TextureAtlas::from_grid_with_padding(
+        texture_handle,
+        Vec2::new(sheet.tile_size, sheet.tile_size),
+        sheet.columns,
+        sheet.rows,
+        Vec2::new(sheet.padding, sheet.padding),
+    )
+}
+
+fn load_sheet(
+    sheet: &SheetConfig,
+    asset_server: &AssetServer,
+    atlases: &mut Assets<TextureAtlas>,
+    materials: &mut Assets<ColorMaterial>,
+) -> (Handle<TextureAtlas>, Handle<ColorMaterial>) {
+    let atlas = sheet_atlas(sheet, asset_server);
+    let material = materials.add(ColorMaterial::texture(atlas.texture.clone()));
+    (atlases.add(atlas), material)
 }
 
 fn setup(
@@ -65,6 +120,8 @@ fn setup(
     mut atlases: ResMut<Assets<TextureAtlas>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
     mut handles: ResMut<SeaHandles>,
+    mut biomes: ResMut<BiomeConfig>,
+    mut ships: ResMut<ShipsConfig>,
 ) {
     //loading textures
     let texture_handle_sea_spritesheet = asset_server.load("sprites/sea/seaTileSheet.png");
@@ -73,41 +130,50 @@ fn setup(
         TextureAtlas::from_grid(texture_handle_sea_spritesheet, Vec2::new(64., 64.), 3, 1);
     handles.sea_sheet = atlases.add(sea_atlas);
 
-    let texture_handle_islands_spritesheet = asset_server.load("sprites/sea/sheet2.png");
-    let islands_atlas = TextureAtlas::from_grid_with_padding(
-        texture_handle_islands_spritesheet,
-        Vec2::new(TILE_SIZE as f32, TILE_SIZE as f32),
-        27,
-        7,
-        Vec2::new(1., 1.),
-    );
-    handles.islands_material = materials.add(ColorMaterial::texture(islands_atlas.texture.clone()));
-    handles.islands_sheet = atlases.add(islands_atlas);
-    let texture_handle = asset_server.load("sprites/sea/ship_sheet.png");
-    let texture_atlas = TextureAtlas::from_grid_with_padding(
-        texture_handle,
-        Vec2::new(133., 133.),
-        8,
-        1,
-        Vec2::new(1., 1.),
-    );
-    let texture_atlas_handle = atlases.add(texture_atlas);
-    handles.boat = texture_atlas_handle;
-    let texture_handle = asset_server.load("sprites/sea/ship_collisions_sheet2.png");
-    let texture_atlas = TextureAtlas::from_grid_with_padding(
-        texture_handle,
-        Vec2::new(133., 133.),
-        8,
-        1,
-        Vec2::new(1., 1.),
-    );
-    let texture_atlas_handle = atlases.add(texture_atlas);
-    handles.boat_collisions = texture_atlas_handle;
+    *biomes = BiomeConfig(Arc::new(
+        read_worldgen_config()
+            .drain(..)
+            .map(|biome| {
+                let (sea_sheet, sea_material) = load_sheet(
+                    &biome.sea_sheet,
+                    &asset_server,
+                    &mut atlases,
+                    &mut materials,
+                );
+                let (land_sheet, land_material) = load_sheet(
+                    &biome.land_sheet,
+                    &asset_server,
+                    &mut atlases,
+                    &mut materials,
+                );
+                let assets = BiomeAssets {
+                    sea_sheet,
+                    sea_material,
+                    land_sheet,
+                    land_material,
+                };
+                (assets, biome)
+            })
+            .collect(),
+    ));
+    *ships = ShipsConfig(Arc::new(
+        read_ships_config()
+            .drain(..)
+            .map(|class| {
+                let assets = ShipAssets {
+                    sheet: atlases.add(sheet_atlas(&class.sprite_sheet, &asset_server)),
+                    collisions: atlases.add(sheet_atlas(&class.collision_sheet, &asset_server)),
+                    meshes: Vec::new(),
+                };
+                (assets, class)
+            })
+            .collect(),
+    ));
 }
 
 fn on_loaded(
     asset_server: Res<AssetServer>,
-    mut handles: ResMut<SeaHandles>,
+    mut ships: ResMut<ShipsConfig>,
     atlases: Res<Assets<TextureAtlas>>,
     textures: Res<Assets<Texture>>,
     mut loaded: Local<bool>,
@@ -115,21 +181,30 @@ fn on_loaded(
     if *loaded {
         return;
     }
-    let texture_atlas = atlases.get(handles.boat_collisions.clone()).unwrap();
-    if asset_server.get_load_state(texture_atlas.texture.clone()) != LoadState::Loaded {
+    let all_loaded = ships.0.iter().all(|(assets, _)| {
+        let texture_atlas = atlases.get(assets.collisions.clone()).unwrap();
+        asset_server.get_load_state(texture_atlas.texture.clone()) == LoadState::Loaded
+    });
+    if !all_loaded {
         return;
-    };
+    }
     *loaded = true;
-    let texture = textures.get(texture_atlas.texture.clone()).unwrap();
-    let trimeshes = texture_atlas_to_trimeshes(texture_atlas, texture, 1. / ISLAND_SCALING);
-    handles.boat_meshes = trimeshes;
+    for (assets, _) in Arc::make_mut(&mut ships.0).iter_mut() {
+        let texture_atlas = atlases.get(assets.collisions.clone()).unwrap();
+        let texture = textures.get(texture_atlas.texture.clone()).unwrap();
+        assets.meshes = texture_atlas_to_trimeshes(texture_atlas, texture, 1. / ISLAND_SCALING);
+    }
 }
 
 fn enter_island_system(
     keyboard_input: Res<Input<KeyCode>>,
     pos_update: Res<PlayerPositionUpdate>,
     mut state: ResMut<State<GameState>>,
+    paused: Res<SeaPaused>,
 ) {
+    if paused.0 {
+        return;
+    }
     if keyboard_input.just_pressed(KeyCode::Return)
         && pos_update.island_id.is_some()
         && state.current() == &GameState::Sea
@@ -2,21 +2,25 @@ use crate::{background::BgFlag, loading::GameState};
 
 use super::{
     super::background::{BackgroundBundle, TileUv},
-    collision::IslandSpawnEvent,
-    loader::SeaHandles,
-    worldgen::Island,
-    ISLAND_SCALING, TILE_SIZE,
+    collision::{island_value, IslandsChangedEvent, SeaCollisionTree},
+    loader::{BiomeConfig, SeaHandles},
+    player::PlayerPositionUpdate,
+    weather::CurrentWeather,
+    worldgen::{Island, IslandId, ISLAND_EVICT_DISTANCE},
+    SeaPaused, ISLAND_SCALING, TILE_SIZE,
 };
 use bevy::{
     prelude::*,
-    render::{camera::Camera, render_graph::base::MainPass},
-    utils::HashSet,
+    render::{
+        camera::{Camera, OrthographicProjection},
+        render_graph::base::MainPass,
+    },
+    utils::{HashMap, HashSet},
 };
 
 use serde::{Deserialize, Serialize};
-use std::time::Duration;
 
-#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum TileKind {
     Sand(bool),
     Forest,
@@ -28,13 +32,43 @@ impl Default for TileKind {
     }
 }
 #[derive(Default)]
-pub struct Islands(pub Vec<Island>);
+pub struct Islands(pub HashMap<IslandId, Island>);
+
+//What the player changed on an island, kept when the island is dropped from memory.
+//Everything else is generated again from the seed.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct IslandDelta {
+    pub visited: bool,
+    //id and position of the mobs still alive, None if the mobs were never disturbed
+    pub mobs: Option<Vec<(u32, [f32; 3])>>,
+}
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct IslandDeltas(pub HashMap<IslandId, IslandDelta>);
+
+//islands are spawned that far out of the screen, so they never pop in, and despawned further, in tiles
+const SPAWN_MARGIN: i32 = 8;
+const DESPAWN_MARGIN: i32 = 24;
+
+//The islands on screen, and the rectangle seen by the camera, in tiles.
+#[derive(Default)]
+pub struct VisibleIslands {
+    pub islands: HashSet<IslandId>,
+    pub min: (i32, i32),
+    pub max: (i32, i32),
+}
 pub struct SeaMapPlugin;
 impl Plugin for SeaMapPlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.on_state_enter(GameState::STAGE, GameState::Sea, load_map_system.system())
             .on_state_exit(GameState::STAGE, GameState::Sea, unload_map_system.system())
             .init_resource::<Islands>()
+            .init_resource::<IslandDeltas>()
+            .init_resource::<VisibleIslands>()
+            .on_state_update(
+                GameState::STAGE,
+                GameState::Sea,
+                evict_islands_system.system(),
+            )
             .on_state_update(
                 GameState::STAGE,
                 GameState::Sea,
@@ -52,19 +86,25 @@ fn move_anim_bg_system(
     mut bg_query: Query<(&mut TileUv, &mut Transform)>,
     camera_query: Query<(&Camera, &Transform)>,
     time: Res<Time>,
-    mut timer: Local<Option<Timer>>,
+    weather: Res<CurrentWeather>,
+    mut elapsed: Local<f32>,
+    paused: Res<SeaPaused>,
 ) {
-    if timer.is_none() {
-        *timer = Some(Timer::new(Duration::from_millis(500), true));
+    if paused.0 {
+        return;
+    }
+    //the waves move faster in bad weather
+    *elapsed += time.delta_seconds();
+    let next_frame = *elapsed >= weather.wave_period;
+    if next_frame {
+        *elapsed = 0.;
     }
-    let timer = timer.as_mut().unwrap();
     for (mut bg, mut bg_transform) in bg_query.iter_mut() {
         for (_, camera_transform) in camera_query.iter() {
             bg_transform.translation.x = camera_transform.translation.x;
             bg_transform.translation.y = camera_transform.translation.y;
         }
-        timer.tick(time.delta_seconds());
-        if timer.finished() {
+        if next_frame {
             bg.uv += Vec2::new(1. / 3., 0.);
             if bg.uv.x >= 0.99 {
                 bg.uv = Vec2::new(0., 0.)
@@ -88,6 +128,7 @@ fn load_map_system(
         tile_uv: TileUv {
             uv: Vec2::new(0.0, 0.0),
             scale: 2.,
+            tint: Color::WHITE,
         },
         ..Default::default()
     });
@@ -101,7 +142,7 @@ fn unload_map_system(
     for entity in bg_query.iter() {
         commands.despawn_recursive(entity);
     }
-    for island in islands.0.iter_mut() {
+    for island in islands.0.values_mut() {
         let entity = island.entity.take();
         if let Some(entity) = entity {
             commands.despawn_recursive(entity);
@@ -168,44 +209,52 @@ impl Default for IslandBundle {
         }
     }
 }
-#[derive(Default)]
-struct SpawnedIslands {
-    new: HashSet<u32>,
-    old: HashSet<u32>,
-}
-impl SpawnedIslands {
-    fn insert(&mut self, el: u32) {
-        self.new.insert(el);
-    }
-    fn get_diff(&self) -> impl Iterator<Item = &u32> {
-        self.old.difference(&self.new)
-    }
-    fn swap(&mut self) {
-        std::mem::swap(&mut self.new, &mut self.old);
-        self.new.clear();
-    }
-}
-
+//Spawns the islands around the screen, hides the ones out of it, and despawns them once far enough.
 fn spawn_island_system(
     commands: &mut Commands,
-    mut event_reader: EventReader<IslandSpawnEvent>,
     mut islands: ResMut<Islands>,
-    mut spawned_islands: Local<SpawnedIslands>,
-    handles: Res<SeaHandles>,
+    mut visible_islands: ResMut<VisibleIslands>,
+    kdtree: Res<SeaCollisionTree>,
+    biomes: Res<BiomeConfig>,
+    camera_query: Query<(&Camera, &OrthographicProjection, &Transform)>,
+    mut visible_query: Query<&mut Visible>,
 ) {
-    for event in event_reader.iter() {
-        let IslandSpawnEvent(island_id) = event;
-        let island = &mut islands.0[*island_id as usize];
-        spawned_islands.insert(*island_id);
+    let (_, projection, camera_transform) = match camera_query.iter().next() {
+        Some(camera) => camera,
+        None => return,
+    };
+    //the corners of the screen, in tiles
+    let tile_size = TILE_SIZE as f32 * ISLAND_SCALING;
+    let scale = camera_transform.scale;
+    let center = camera_transform.translation;
+    let min = (
+        ((center.x + projection.left * scale.x) / tile_size).floor() as i32,
+        ((center.y + projection.bottom * scale.y) / tile_size).floor() as i32,
+    );
+    let max = (
+        ((center.x + projection.right * scale.x) / tile_size).ceil() as i32,
+        ((center.y + projection.top * scale.y) / tile_size).ceil() as i32,
+    );
+    visible_islands.min = min;
+    visible_islands.max = max;
+    visible_islands.islands.clear();
+    for value in kdtree.0.query_rect(min.0, max.0, min.1, max.1) {
+        visible_islands.islands.insert(value.island_id);
+    }
+
+    for value in kdtree.0.query_rect(
+        min.0 - SPAWN_MARGIN,
+        max.0 + SPAWN_MARGIN,
+        min.1 - SPAWN_MARGIN,
+        max.1 + SPAWN_MARGIN,
+    ) {
+        let island = match islands.0.get_mut(&value.island_id) {
+            Some(island) => island,
+            None => continue,
+        };
         if island.entity.is_some() {
             continue;
         }
-        // println!(
-        //     "Island {} spawned at {} {}",
-        //     island_id,
-        //     island.min_x as f32 * 16.,
-        //     island.min_y as f32 * 16.
-        // );
         let entity = commands
             .spawn(IslandBundle {
                 mesh: island.mesh.clone(),
@@ -218,17 +267,82 @@ fn spawn_island_system(
                     scale: ISLAND_SCALING * Vec3::one(),
                     ..Default::default()
                 },
-                material: handles.islands_material.clone(),
+                material: biomes.0[island.biome].0.sea_material.clone(),
                 ..Default::default()
             })
             .current_entity();
         island.entity = entity;
     }
-    for island_id in spawned_islands.get_diff() {
-        let island = &mut islands.0[*island_id as usize];
-        if let Some(entity) = island.entity.take() {
+
+    for island in islands.0.values_mut() {
+        let entity = match island.entity {
+            Some(entity) => entity,
+            None => continue,
+        };
+        let far = island.max_x < min.0 - DESPAWN_MARGIN
+            || island.min_x > max.0 + DESPAWN_MARGIN
+            || island.max_y < min.1 - DESPAWN_MARGIN
+            || island.min_y > max.1 + DESPAWN_MARGIN;
+        if far {
             commands.despawn_recursive(entity);
+            island.entity = None;
+        } else if let Ok(mut visible) = visible_query.get_mut(entity) {
+            //out of the screen, they are kept but not drawn
+            visible.is_visible = visible_islands.islands.contains(&island.id);
         }
     }
-    spawned_islands.swap();
+}
+
+//Drops the islands far from the player, their delta is saved so they come back as they were left.
+fn evict_islands_system(
+    commands: &mut Commands,
+    player_pos: Res<PlayerPositionUpdate>,
+    mut islands: ResMut<Islands>,
+    mut deltas: ResMut<IslandDeltas>,
+    mut kdtree: ResMut<SeaCollisionTree>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut changed_events: ResMut<Events<IslandsChangedEvent>>,
+) {
+    let distance = |island: &Island| {
+        let dx = (island.min_x - player_pos.x).max(player_pos.x - island.max_x);
+        let dy = (island.min_y - player_pos.y).max(player_pos.y - island.max_y);
+        dx.max(dy)
+    };
+    let far_islands: Vec<IslandId> = islands
+        .0
+        .values()
+        .filter(|island| distance(island) > ISLAND_EVICT_DISTANCE)
+        .map(|island| island.id)
+        .collect();
+    if far_islands.is_empty() {
+        return;
+    }
+    for id in far_islands {
+        let island = islands.0.remove(&id).unwrap();
+        changed_events.send(IslandsChangedEvent {
+            min: (island.min_x, island.min_y),
+            max: (island.max_x, island.max_y),
+        });
+        if let Some(entity) = island.entity {
+            commands.despawn_recursive(entity);
+        }
+        meshes.remove(&island.mesh);
+        if let Some(delta) = deltas.0.get_mut(&id) {
+            //mobs only move while the player is on the island
+            if delta.visited && island.features_generated {
+                delta.mobs = Some(
+                    island
+                        .mobs
+                        .iter()
+                        .map(|(mob, transform)| (mob.id, transform.translation.into()))
+                        .collect(),
+                );
+            }
+        }
+    }
+    //the tree can't remove values, it is rebuilt with the remaining islands
+    kdtree.0 = Default::default();
+    for island in islands.0.values() {
+        kdtree.0.insert(island_value(island));
+    }
 }
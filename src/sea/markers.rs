@@ -0,0 +1,243 @@
+use bevy::{prelude::*, render::camera::Camera};
+use bevy_egui::{egui, EguiContext};
+use egui::Visuals;
+use serde::{Deserialize, Serialize};
+
+use crate::loading::GameState;
+
+use super::{
+    collision::SeaCollisionTree,
+    exploration::Exploration,
+    map::{Islands, TileKind},
+    navigation::{Route, SeaNavigation},
+    player::PlayerPositionUpdate,
+    worldgen::IslandId,
+    SeaPaused, ISLAND_SCALING, TILE_SIZE,
+};
+
+//the waypoint is reached that close, in tiles
+const ARRIVAL_DISTANCE: f32 = 4.;
+//distance between the compass and the edge of the screen, in points
+const COMPASS_MARGIN: f32 = 40.;
+const COMPASS_SIZE: f32 = 48.;
+//how long the arrival notice stays, in seconds
+const ARRIVAL_NOTICE_TIME: f32 = 4.;
+
+pub struct SeaMarkersPlugin;
+impl Plugin for SeaMarkersPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Markers>()
+            .add_event::<WaypointReachedEvent>()
+            .on_state_update(GameState::STAGE, GameState::Sea, arrival_system.system())
+            .on_state_update(GameState::STAGE, GameState::Sea, compass_ui_system.system())
+            .on_state_update(GameState::STAGE, GameState::Sea, arrival_ui_system.system());
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Marker {
+    pub id: u32,
+    pub name: String,
+    //in tiles
+    pub position: (f32, f32),
+    //the island the marker was dropped on
+    pub island: Option<IslandId>,
+    //the way there, when it is the waypoint
+    #[serde(skip)]
+    route: Route,
+}
+
+//The markers dropped by the player on the world map, one of them can be the waypoint.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Markers {
+    pub markers: Vec<Marker>,
+    pub waypoint: Option<u32>,
+    next_id: u32,
+}
+
+impl Markers {
+    //Drops a marker, on the charted island under it if there is one.
+    pub fn add(&mut self, name: String, position: Vec2, exploration: &Exploration) -> u32 {
+        let tile = (position.x.floor() as i32, position.y.floor() as i32);
+        let island = exploration
+            .islands
+            .iter()
+            .find(|(_, island)| {
+                let kind = island
+                    .tiles
+                    .get((tile.0 - island.min_x) as usize)
+                    .and_then(|column| column.get((tile.1 - island.min_y) as usize));
+                tile.0 >= island.min_x
+                    && tile.1 >= island.min_y
+                    && matches!(kind, Some(TileKind::Sand(_)) | Some(TileKind::Forest))
+            })
+            .map(|(id, _)| *id);
+        let id = self.next_id;
+        self.next_id += 1;
+        self.markers.push(Marker {
+            id,
+            name,
+            position: (position.x, position.y),
+            island,
+            route: Route::default(),
+        });
+        id
+    }
+
+    pub fn remove(&mut self, id: u32) {
+        self.markers.retain(|marker| marker.id != id);
+        if self.waypoint == Some(id) {
+            self.waypoint = None;
+        }
+    }
+
+    pub fn waypoint(&self) -> Option<&Marker> {
+        let id = self.waypoint?;
+        self.markers.iter().find(|marker| marker.id == id)
+    }
+
+    fn waypoint_mut(&mut self) -> Option<&mut Marker> {
+        let id = self.waypoint?;
+        self.markers.iter_mut().find(|marker| marker.id == id)
+    }
+}
+
+pub struct WaypointReachedEvent(pub u32);
+
+fn player_tile(pos_update: &PlayerPositionUpdate) -> Vec2 {
+    pos_update.translation.truncate() / (TILE_SIZE as f32 * ISLAND_SCALING)
+}
+
+//The waypoint is cleared when the player reaches it, or lands on its island.
+fn arrival_system(
+    pos_update: Res<PlayerPositionUpdate>,
+    mut markers: ResMut<Markers>,
+    mut reached_events: ResMut<Events<WaypointReachedEvent>>,
+    paused: Res<SeaPaused>,
+) {
+    if paused.0 {
+        return;
+    }
+    let reached = markers.waypoint().filter(|marker| {
+        let target = Vec2::new(marker.position.0, marker.position.1);
+        let on_island = marker.island.is_some() && marker.island == pos_update.island_id;
+        (target - player_tile(&pos_update)).length() < ARRIVAL_DISTANCE || on_island
+    });
+    let reached = reached.map(|marker| marker.id);
+    if let Some(id) = reached {
+        markers.waypoint = None;
+        reached_events.send(WaypointReachedEvent(id));
+    }
+}
+
+//An arrow at the edge of the screen points the way to the waypoint, around the islands.
+//It sits on the waypoint when the waypoint is on the screen.
+fn compass_ui_system(
+    mut egui_context: ResMut<EguiContext>,
+    windows: Res<Windows>,
+    mut markers: ResMut<Markers>,
+    pos_update: Res<PlayerPositionUpdate>,
+    islands: Res<Islands>,
+    kdtree: Res<SeaCollisionTree>,
+    mut navigation: ResMut<SeaNavigation>,
+    paused: Res<SeaPaused>,
+    camera_query: Query<(&Camera, &Transform)>,
+) {
+    if paused.0 {
+        return;
+    }
+    let (_, camera_transform) = match camera_query.iter().next() {
+        Some(camera) => camera,
+        None => return,
+    };
+    let marker = match markers.waypoint_mut() {
+        Some(marker) => marker,
+        None => return,
+    };
+    let window = match windows.get_primary() {
+        Some(window) => window,
+        None => return,
+    };
+    let tile_size = TILE_SIZE as f32 * ISLAND_SCALING;
+    let player = pos_update.translation.truncate();
+    let target = Vec2::new(marker.position.0, marker.position.1) * tile_size;
+    let next = navigation
+        .follow(&mut marker.route, &islands, &kdtree, player, target)
+        .unwrap_or(target);
+    if (next - player).length() < f32::EPSILON {
+        return;
+    }
+    let direction = (next - player).normalize();
+    let distance = (target - player).length() / tile_size;
+    //the arrow sits where the direction leaves the screen, in points from its center
+    let to_screen = |world: Vec2| {
+        (world - camera_transform.translation.truncate()) / camera_transform.scale.truncate()
+    };
+    let origin = to_screen(player);
+    let half = Vec2::new(
+        window.width() / 2. - COMPASS_MARGIN,
+        window.height() / 2. - COMPASS_MARGIN,
+    );
+    let reach_x = (half.x - origin.x * direction.x.signum()) / direction.x.abs();
+    let reach_y = (half.y - origin.y * direction.y.signum()) / direction.y.abs();
+    let reach = reach_x.min(reach_y).max(0.);
+    let edge = origin + direction * reach.min((to_screen(target) - origin).length());
+    //the screen y axis goes down
+    let position = egui::pos2(
+        window.width() / 2. + edge.x - COMPASS_SIZE / 2.,
+        window.height() / 2. - edge.y - COMPASS_SIZE / 2.,
+    );
+    let ctx = &mut egui_context.ctx;
+    egui::Area::new("compass")
+        .fixed_pos(position)
+        .show(ctx, |ui| {
+            *ui.visuals_mut() = Visuals::light();
+            let (rect, _) = ui
+                .allocate_exact_size(egui::vec2(COMPASS_SIZE, COMPASS_SIZE), egui::Sense::hover());
+            let center = rect.center();
+            let to_screen = |v: Vec2| center + egui::vec2(v.x, -v.y) * (COMPASS_SIZE / 2. - 4.);
+            let side = Vec2::new(-direction.y, direction.x) * 0.4;
+            let stroke = (3., egui::Color32::from_rgb(200, 30, 30));
+            let painter = ui.painter();
+            painter.line_segment([to_screen(-direction), to_screen(direction)], stroke);
+            painter.line_segment(
+                [to_screen(direction), to_screen(direction * 0.4 + side)],
+                stroke,
+            );
+            painter.line_segment(
+                [to_screen(direction), to_screen(direction * 0.4 - side)],
+                stroke,
+            );
+            ui.label(format!("{} {:.0}", marker.name, distance));
+        });
+}
+
+fn arrival_ui_system(
+    mut egui_context: ResMut<EguiContext>,
+    time: Res<Time>,
+    markers: Res<Markers>,
+    mut reached_reader: EventReader<WaypointReachedEvent>,
+    mut notice: Local<(f32, String)>,
+) {
+    for WaypointReachedEvent(id) in reached_reader.iter() {
+        if let Some(marker) = markers.markers.iter().find(|marker| marker.id == *id) {
+            *notice = (ARRIVAL_NOTICE_TIME, marker.name.clone());
+        }
+    }
+    if notice.0 <= 0. {
+        return;
+    }
+    notice.0 -= time.delta_seconds();
+    let ctx = &mut egui_context.ctx;
+    egui::Area::new("arrival")
+        .fixed_pos(egui::pos2(200.0, 60.0))
+        .show(ctx, |ui| {
+            *ui.visuals_mut() = Visuals::light();
+
+            egui::Frame::group(ui.style())
+                .fill(egui::Color32::WHITE)
+                .show(ui, |ui| {
+                    ui.label(format!("You reached {}.", notice.1));
+                });
+        });
+}
@@ -0,0 +1,227 @@
+use bevy::{prelude::*, utils::HashMap};
+use bevy_egui::{egui, EguiContext};
+
+use crate::loading::GameState;
+
+use super::{
+    collision::IslandsChangedEvent,
+    exploration::Exploration,
+    map::{Islands, TileKind},
+    markers::Markers,
+    player::{Player, PlayerPositionUpdate},
+    sailing::ShipMotion,
+    worldgen::{Island, IslandId},
+    SeaPaused, ISLAND_SCALING, TILE_SIZE,
+};
+
+//tiles shown around the player, in each direction
+const MINIMAP_RANGE: f32 = 64.;
+const DEFAULT_SIZE: f32 = 160.;
+const MIN_SIZE: f32 = 64.;
+
+const SEA_COLOR: egui::Color32 = egui::Color32::from_rgb(70, 130, 180);
+const SAND_COLOR: egui::Color32 = egui::Color32::from_rgb(230, 210, 150);
+const FOREST_COLOR: egui::Color32 = egui::Color32::from_rgb(60, 130, 60);
+const PLAYER_COLOR: egui::Color32 = egui::Color32::WHITE;
+const MARKER_COLOR: egui::Color32 = egui::Color32::from_rgb(250, 200, 40);
+
+pub struct SeaMinimapPlugin;
+impl Plugin for SeaMinimapPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Minimap>()
+            .on_state_update(
+                GameState::STAGE,
+                GameState::Sea,
+                minimap_cache_system.system(),
+            )
+            .on_state_update(GameState::STAGE, GameState::Sea, minimap_ui_system.system());
+    }
+}
+
+//Shows an entity on the minimap, as a dot of that color.
+pub struct MinimapIcon(pub Color);
+
+//A row of tiles of the same kind, from x to x + length, in tiles.
+struct Run {
+    x: i32,
+    y: i32,
+    length: i32,
+    color: egui::Color32,
+}
+
+//The islands are turned into runs once, when they are loaded, the minimap only places them.
+pub struct Minimap {
+    pub visible: bool,
+    runs: HashMap<IslandId, Vec<Run>>,
+}
+impl Default for Minimap {
+    fn default() -> Self {
+        Minimap {
+            visible: true,
+            runs: HashMap::default(),
+        }
+    }
+}
+
+fn island_runs(island: &Island) -> Vec<Run> {
+    let color = |kind: TileKind| match kind {
+        TileKind::Sand(_) => Some(SAND_COLOR),
+        TileKind::Forest => Some(FOREST_COLOR),
+        TileKind::Sea(_) => None,
+    };
+    let height = island.tiles.first().map(|column| column.len()).unwrap_or(0);
+    let mut runs = Vec::new();
+    for j in 0..height {
+        let mut current: Option<Run> = None;
+        for (i, column) in island.tiles.iter().enumerate() {
+            let tile_color = color(column[j].kind);
+            match (&mut current, tile_color) {
+                (Some(run), Some(tile_color)) if run.color == tile_color => run.length += 1,
+                _ => {
+                    runs.extend(current.take());
+                    current = tile_color.map(|color| Run {
+                        x: island.min_x + i as i32,
+                        y: island.min_y + j as i32,
+                        length: 1,
+                        color,
+                    });
+                }
+            }
+        }
+        runs.extend(current);
+    }
+    runs
+}
+
+//N toggles the minimap. When the islands change, the runs of the new islands are computed,
+//the ones of the islands dropped are forgotten.
+fn minimap_cache_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    islands: Res<Islands>,
+    mut minimap: ResMut<Minimap>,
+    mut changed_reader: EventReader<IslandsChangedEvent>,
+) {
+    if keyboard_input.just_pressed(KeyCode::N) {
+        minimap.visible = !minimap.visible;
+    }
+    if changed_reader.iter().count() == 0 {
+        return;
+    }
+    minimap.runs.retain(|id, _| islands.0.contains_key(id));
+    for (id, island) in islands.0.iter() {
+        if !minimap.runs.contains_key(id) {
+            minimap.runs.insert(*id, island_runs(island));
+        }
+    }
+}
+
+//Only what the player has seen is drawn.
+fn minimap_ui_system(
+    mut egui_context: ResMut<EguiContext>,
+    minimap: Res<Minimap>,
+    exploration: Res<Exploration>,
+    markers: Res<Markers>,
+    islands: Res<Islands>,
+    pos_update: Res<PlayerPositionUpdate>,
+    player_query: Query<&ShipMotion, With<Player>>,
+    icon_query: Query<(&MinimapIcon, &Transform)>,
+    paused: Res<SeaPaused>,
+) {
+    if paused.0 || !minimap.visible {
+        return;
+    }
+    let tile_size = TILE_SIZE as f32 * ISLAND_SCALING;
+    let center = pos_update.translation.truncate() / tile_size;
+    let ctx = &mut egui_context.ctx;
+    egui::Window::new("Minimap")
+        .default_pos(egui::pos2(1000., 4.))
+        .default_size(egui::vec2(DEFAULT_SIZE, DEFAULT_SIZE))
+        .resizable(true)
+        .show(ctx, |ui| {
+            let available = ui.available_size();
+            let side = available.x.min(available.y).max(MIN_SIZE);
+            let (rect, _) = ui.allocate_exact_size(egui::vec2(side, side), egui::Sense::hover());
+            let scale = side / (2. * MINIMAP_RANGE);
+            //the screen y axis goes down
+            let to_screen =
+                |x: f32, y: f32| rect.center() + egui::vec2(x - center.x, center.y - y) * scale;
+            let painter = ui.painter();
+            painter.rect_filled(rect, 0., SEA_COLOR);
+            for (id, runs) in minimap.runs.iter() {
+                let island = match islands.0.get(id) {
+                    Some(island) => island,
+                    None => continue,
+                };
+                if (island.max_x as f32) < center.x - MINIMAP_RANGE
+                    || (island.min_x as f32) > center.x + MINIMAP_RANGE
+                    || (island.max_y as f32) < center.y - MINIMAP_RANGE
+                    || (island.min_y as f32) > center.y + MINIMAP_RANGE
+                {
+                    continue;
+                }
+                let draw = |x: i32, y: i32, length: i32, color| {
+                    let run_rect = egui::Rect::from_min_max(
+                        to_screen(x as f32, (y + 1) as f32),
+                        to_screen((x + length) as f32, y as f32),
+                    )
+                    .intersect(rect);
+                    if run_rect.width() > 0. && run_rect.height() > 0. {
+                        painter.rect_filled(run_rect, 0., color);
+                    }
+                };
+                //the runs are cut where the fog is
+                for run in runs.iter() {
+                    let mut start = None;
+                    for x in run.x..=run.x + run.length {
+                        let revealed =
+                            x < run.x + run.length && exploration.is_revealed((x, run.y));
+                        match (revealed, start) {
+                            (true, None) => start = Some(x),
+                            (false, Some(from)) => {
+                                draw(from, run.y, x - from, run.color);
+                                start = None;
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+            }
+            for (icon, transform) in icon_query.iter() {
+                let position = transform.translation.truncate() / tile_size;
+                let tile = (position.x.floor() as i32, position.y.floor() as i32);
+                let position = to_screen(position.x, position.y);
+                if rect.contains(position) && exploration.is_revealed(tile) {
+                    let color = icon.0;
+                    painter.circle_filled(
+                        position,
+                        2.5,
+                        egui::Color32::from_rgb(
+                            (color.r() * 255.) as u8,
+                            (color.g() * 255.) as u8,
+                            (color.b() * 255.) as u8,
+                        ),
+                    );
+                }
+            }
+            //the markers out of range stay on the border, in their direction
+            for marker in markers.markers.iter() {
+                let position = to_screen(marker.position.0, marker.position.1);
+                let position = egui::pos2(
+                    position.x.max(rect.left()).min(rect.right()),
+                    position.y.max(rect.top()).min(rect.bottom()),
+                );
+                painter.circle_filled(position, 3., MARKER_COLOR);
+            }
+            //the player points where its sprite points
+            if let Some(motion) = player_query.iter().next() {
+                let heading = motion.heading();
+                let position = rect.center();
+                let tip = position + egui::vec2(heading.x, -heading.y) * 8.;
+                let side = egui::vec2(-heading.y, -heading.x) * 3.;
+                let back = position - egui::vec2(heading.x, -heading.y) * 4.;
+                painter.line_segment([back + side, tip], (2., PLAYER_COLOR));
+                painter.line_segment([back - side, tip], (2., PLAYER_COLOR));
+                painter.line_segment([back + side, back - side], (2., PLAYER_COLOR));
+            }
+        });
+}
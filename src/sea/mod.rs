@@ -1,15 +1,43 @@
 use bevy::prelude::*;
 
 //pub(crate) mod collision;
+pub(crate) mod autotile;
 pub mod collision;
+pub mod combat;
+pub mod currents;
+pub mod exploration;
 pub mod loader;
 pub(crate) mod map;
+pub mod markers;
+pub mod minimap;
+pub mod navigation;
+pub mod npc;
 pub mod player;
-pub(crate) mod worldgen;
+pub(crate) mod regions;
+pub mod sailing;
+pub(crate) mod shape;
+pub mod ships;
+pub(crate) mod solver;
+pub(crate) mod ui;
+pub mod weather;
+pub(crate) mod wind;
+pub(crate) mod world_map;
+pub mod worldgen;
 //use collision::SeaCollisionPlugin;
+use combat::SeaCombatPlugin;
+use currents::SeaCurrentsPlugin;
+use exploration::SeaExplorationPlugin;
 use loader::SeaLoaderPlugin;
 use map::SeaMapPlugin;
+use markers::SeaMarkersPlugin;
+use minimap::SeaMinimapPlugin;
+use navigation::SeaNavigationPlugin;
+use npc::SeaNpcPlugin;
 use player::SeaPlayerPlugin;
+use ui::SeaUiPlugin;
+use weather::SeaWeatherPlugin;
+use wind::SeaWindPlugin;
+use world_map::SeaWorldMapPlugin;
 use worldgen::SeaWorldGenPlugin;
 
 use self::collision::SeaCollisionPlugin;
@@ -18,12 +46,28 @@ pub struct SeaPlugin;
 
 pub const TILE_SIZE: i32 = 16;
 
+//Set while the world map is open over the sea, nothing moves at sea.
+#[derive(Default)]
+pub struct SeaPaused(pub bool);
+
 impl Plugin for SeaPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.add_plugin(SeaLoaderPlugin)
+        app.init_resource::<SeaPaused>()
+            .add_plugin(SeaLoaderPlugin)
             .add_plugin(SeaPlayerPlugin)
             .add_plugin(SeaMapPlugin)
             .add_plugin(SeaCollisionPlugin)
-            .add_plugin(SeaWorldGenPlugin);
+            .add_plugin(SeaWorldGenPlugin)
+            .add_plugin(SeaWindPlugin)
+            .add_plugin(SeaWeatherPlugin)
+            .add_plugin(SeaCurrentsPlugin)
+            .add_plugin(SeaNavigationPlugin)
+            .add_plugin(SeaCombatPlugin)
+            .add_plugin(SeaNpcPlugin)
+            .add_plugin(SeaExplorationPlugin)
+            .add_plugin(SeaWorldMapPlugin)
+            .add_plugin(SeaMinimapPlugin)
+            .add_plugin(SeaMarkersPlugin)
+            .add_plugin(SeaUiPlugin);
     }
 }
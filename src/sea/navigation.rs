@@ -0,0 +1,419 @@
+use std::{cmp::Reverse, collections::BinaryHeap, sync::Arc};
+
+use bevy::{prelude::*, utils::HashMap};
+use parry2d::math::Point;
+
+use crate::loading::GameState;
+
+use super::{
+    collision::{IslandsChangedEvent, SeaCollisionTree},
+    map::Islands,
+    shape::IslandCollider,
+    ISLAND_SCALING, TILE_SIZE,
+};
+
+//size of a cell of the navigation grid, in tiles
+const CELL_SIZE: i32 = 2;
+//the ships keep that far from the islands, in tiles
+const CLEARANCE: f32 = 1.;
+//the search gives up after that many cells, the destination is too far or can't be reached
+const MAX_EXPANDED_CELLS: usize = 6000;
+const MAX_CACHED_PATHS: usize = 256;
+//past that many blocked cells known, the ones far from the ships are forgotten, in cells
+const MAX_BLOCKED_CELLS: usize = 1 << 16;
+const BLOCKED_EVICT_DISTANCE: i32 = 64;
+//a waypoint is reached that close, and a route is left that far from it, in world units
+const WAYPOINT_REACHED: f32 = (CELL_SIZE * TILE_SIZE) as f32 * ISLAND_SCALING;
+const OFF_ROUTE_DISTANCE: f32 = 3. * WAYPOINT_REACHED;
+//costs of a straight and a diagonal step
+const STRAIGHT_COST: u32 = 10;
+const DIAGONAL_COST: u32 = 14;
+
+pub struct SeaNavigationPlugin;
+impl Plugin for SeaNavigationPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<SeaNavigation>().on_state_update(
+            GameState::STAGE,
+            GameState::Sea,
+            invalidate_system.system(),
+        );
+    }
+}
+
+type Cell = (i32, i32);
+
+//A path found, with the cells it goes through, so it is only forgotten when the islands there change.
+struct CachedPath {
+    path: Option<Arc<Vec<Vec2>>>,
+    min: Cell,
+    max: Cell,
+}
+
+//Plans the routes at sea on a coarse grid around the islands known to the collision tree.
+//The blocked cells and the paths found are cached, and forgotten when islands come and go.
+#[derive(Default)]
+pub struct SeaNavigation {
+    blocked: HashMap<Cell, bool>,
+    paths: HashMap<(Cell, Cell), CachedPath>,
+}
+
+//A path being sailed. It is only planned again when the ship leaves it,
+//when the destination moves to another cell or when the islands along it change.
+#[derive(Debug, Default, Clone)]
+pub struct Route {
+    key: Option<(Cell, Cell)>,
+    path: Option<Arc<Vec<Vec2>>>,
+    origin: Vec2,
+    destination: Vec2,
+    next: usize,
+}
+
+impl SeaNavigation {
+    //A path from a world position to another, as world positions, starting with the first waypoint to sail to.
+    //Cells that aren't generated yet are open sea. None if there is no way.
+    pub fn find_path(
+        &mut self,
+        islands: &Islands,
+        kdtree: &SeaCollisionTree,
+        from: Vec2,
+        to: Vec2,
+    ) -> Option<Arc<Vec<Vec2>>> {
+        self.plan(islands, kdtree, from, to).1
+    }
+
+    //The next point to sail to on a route, planned again only when needed.
+    pub fn follow(
+        &mut self,
+        route: &mut Route,
+        islands: &Islands,
+        kdtree: &SeaCollisionTree,
+        from: Vec2,
+        to: Vec2,
+    ) -> Option<Vec2> {
+        let cached = route
+            .key
+            .and_then(|key| self.paths.get(&key))
+            .map(|cached| match (&cached.path, &route.path) {
+                (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+                (None, None) => true,
+                _ => false,
+            })
+            .unwrap_or(false);
+        if !cached || cell_of(route.destination) != cell_of(to) || route.left(from) {
+            let (key, path) = self.plan(islands, kdtree, from, to);
+            *route = Route {
+                key: Some(key),
+                path,
+                origin: from,
+                destination: to,
+                next: 0,
+            };
+        }
+        let path = route.path.as_ref()?;
+        while route.next + 1 < path.len() && (path[route.next] - from).length() < WAYPOINT_REACHED {
+            route.next += 1;
+        }
+        Some(path.get(route.next).copied().unwrap_or(to))
+    }
+
+    fn plan(
+        &mut self,
+        islands: &Islands,
+        kdtree: &SeaCollisionTree,
+        from: Vec2,
+        to: Vec2,
+    ) -> ((Cell, Cell), Option<Arc<Vec<Vec2>>>) {
+        let start = cell_of(from);
+        if self.blocked.len() > MAX_BLOCKED_CELLS {
+            self.blocked.retain(|cell, _| {
+                (cell.0 - start.0).abs().max((cell.1 - start.1).abs()) <= BLOCKED_EVICT_DISTANCE
+            });
+        }
+        let goal = self.free_goal(islands, kdtree, start, cell_of(to));
+        if let Some(cached) = self.paths.get(&(start, goal)) {
+            return ((start, goal), cached.path.clone());
+        }
+        let path = self.search(islands, kdtree, start, goal).map(|cells| {
+            let mut waypoints: Vec<Vec2> = self
+                .smooth(islands, kdtree, &cells)
+                .iter()
+                .skip(1)
+                .map(|cell| cell_center(*cell))
+                .collect();
+            //the last waypoint is the destination itself when it can be reached
+            if goal == cell_of(to) {
+                waypoints.pop();
+                waypoints.push(to);
+            }
+            Arc::new(waypoints)
+        });
+        if self.paths.len() >= MAX_CACHED_PATHS {
+            self.paths.clear();
+        }
+        let (mut min, mut max) = (start, start);
+        let waypoints = path
+            .iter()
+            .flat_map(|path| path.iter())
+            .map(|p| cell_of(*p));
+        for cell in waypoints.chain(std::iter::once(goal)) {
+            min = (min.0.min(cell.0), min.1.min(cell.1));
+            max = (max.0.max(cell.0), max.1.max(cell.1));
+        }
+        let cached = CachedPath {
+            path: path.clone(),
+            min,
+            max,
+        };
+        self.paths.insert((start, goal), cached);
+        ((start, goal), path)
+    }
+
+    //The next point to sail to on the way to a destination, the destination itself if nothing is in the way.
+    pub fn next_waypoint(
+        &mut self,
+        islands: &Islands,
+        kdtree: &SeaCollisionTree,
+        from: Vec2,
+        to: Vec2,
+    ) -> Option<Vec2> {
+        let path = self.find_path(islands, kdtree, from, to)?;
+        Some(path.first().copied().unwrap_or(to))
+    }
+
+    fn is_blocked(&mut self, islands: &Islands, kdtree: &SeaCollisionTree, cell: Cell) -> bool {
+        *self
+            .blocked
+            .entry(cell)
+            .or_insert_with(|| cell_blocked(islands, kdtree, cell))
+    }
+
+    //Destinations in an island are moved back to the coast, on the side of the start.
+    fn free_goal(
+        &mut self,
+        islands: &Islands,
+        kdtree: &SeaCollisionTree,
+        start: Cell,
+        goal: Cell,
+    ) -> Cell {
+        let steps = (goal.0 - start.0).abs().max((goal.1 - start.1).abs());
+        for i in (0..=steps).rev() {
+            let t = if steps == 0 {
+                0.
+            } else {
+                i as f32 / steps as f32
+            };
+            let cell = (
+                start.0 + ((goal.0 - start.0) as f32 * t).round() as i32,
+                start.1 + ((goal.1 - start.1) as f32 * t).round() as i32,
+            );
+            if !self.is_blocked(islands, kdtree, cell) {
+                return cell;
+            }
+        }
+        start
+    }
+
+    //A* on the cells, the diagonals can't cut the corners of the islands.
+    fn search(
+        &mut self,
+        islands: &Islands,
+        kdtree: &SeaCollisionTree,
+        start: Cell,
+        goal: Cell,
+    ) -> Option<Vec<Cell>> {
+        let heuristic = |cell: Cell| {
+            let dx = (cell.0 - goal.0).abs() as u32;
+            let dy = (cell.1 - goal.1).abs() as u32;
+            STRAIGHT_COST * dx.max(dy) + (DIAGONAL_COST - STRAIGHT_COST) * dx.min(dy)
+        };
+        let mut open = BinaryHeap::new();
+        let mut costs: HashMap<Cell, u32> = HashMap::default();
+        let mut parents: HashMap<Cell, Cell> = HashMap::default();
+        open.push(Reverse((heuristic(start), start)));
+        costs.insert(start, 0);
+        let mut expanded = 0;
+        while let Some(Reverse((_, cell))) = open.pop() {
+            if cell == goal {
+                let mut path = vec![cell];
+                let mut current = cell;
+                while let Some(parent) = parents.get(&current) {
+                    current = *parent;
+                    path.push(current);
+                }
+                path.reverse();
+                return Some(path);
+            }
+            expanded += 1;
+            if expanded > MAX_EXPANDED_CELLS {
+                return None;
+            }
+            let cost = costs[&cell];
+            for dx in -1..=1 {
+                for dy in -1..=1 {
+                    if dx == 0 && dy == 0 {
+                        continue;
+                    }
+                    let next = (cell.0 + dx, cell.1 + dy);
+                    if self.is_blocked(islands, kdtree, next) {
+                        continue;
+                    }
+                    let step = if dx != 0 && dy != 0 {
+                        if self.is_blocked(islands, kdtree, (cell.0 + dx, cell.1))
+                            || self.is_blocked(islands, kdtree, (cell.0, cell.1 + dy))
+                        {
+                            continue;
+                        }
+                        DIAGONAL_COST
+                    } else {
+                        STRAIGHT_COST
+                    };
+                    let next_cost = cost + step;
+                    if costs.get(&next).map(|c| next_cost < *c).unwrap_or(true) {
+                        costs.insert(next, next_cost);
+                        parents.insert(next, cell);
+                        open.push(Reverse((next_cost + heuristic(next), next)));
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    //Keeps the cells where the path has to turn: from each kept cell, the furthest one in sight.
+    fn smooth(
+        &mut self,
+        islands: &Islands,
+        kdtree: &SeaCollisionTree,
+        cells: &[Cell],
+    ) -> Vec<Cell> {
+        let mut smoothed = vec![cells[0]];
+        let mut i = 0;
+        while i + 1 < cells.len() {
+            let mut furthest = i + 1;
+            for j in (i + 2..cells.len()).rev() {
+                if self.in_sight(islands, kdtree, cells[i], cells[j]) {
+                    furthest = j;
+                    break;
+                }
+            }
+            smoothed.push(cells[furthest]);
+            i = furthest;
+        }
+        smoothed
+    }
+
+    fn in_sight(&mut self, islands: &Islands, kdtree: &SeaCollisionTree, a: Cell, b: Cell) -> bool {
+        //two samples per cell, so no corner is skipped
+        let steps = 2 * (b.0 - a.0).abs().max((b.1 - a.1).abs());
+        (0..=steps).all(|i| {
+            let t = i as f32 / steps.max(1) as f32;
+            let x = a.0 as f32 + 0.5 + (b.0 - a.0) as f32 * t;
+            let y = a.1 as f32 + 0.5 + (b.1 - a.1) as f32 * t;
+            !self.is_blocked(islands, kdtree, (x.floor() as i32, y.floor() as i32))
+        })
+    }
+}
+
+impl Route {
+    //too far from the leg being sailed
+    fn left(&self, position: Vec2) -> bool {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return false,
+        };
+        let to = match path.get(self.next) {
+            Some(to) => *to,
+            None => return false,
+        };
+        let from = if self.next == 0 {
+            self.origin
+        } else {
+            path[self.next - 1]
+        };
+        let leg = to - from;
+        let t = if leg.length_squared() > f32::EPSILON {
+            ((position - from).dot(leg) / leg.length_squared())
+                .max(0.)
+                .min(1.)
+        } else {
+            0.
+        };
+        (from + leg * t - position).length() > OFF_ROUTE_DISTANCE
+    }
+}
+
+fn cell_of(position: Vec2) -> Cell {
+    let cell_size = (CELL_SIZE * TILE_SIZE) as f32 * ISLAND_SCALING;
+    (
+        (position.x / cell_size).floor() as i32,
+        (position.y / cell_size).floor() as i32,
+    )
+}
+
+fn cell_center((x, y): Cell) -> Vec2 {
+    let cell_size = (CELL_SIZE * TILE_SIZE) as f32 * ISLAND_SCALING;
+    Vec2::new((x as f32 + 0.5) * cell_size, (y as f32 + 0.5) * cell_size)
+}
+
+//A cell is blocked when it is too close to the rigid or friction shape of an island.
+fn cell_blocked(islands: &Islands, kdtree: &SeaCollisionTree, (x, y): Cell) -> bool {
+    let (min_x, min_y) = (x * CELL_SIZE, y * CELL_SIZE);
+    let (max_x, max_y) = (min_x + CELL_SIZE, min_y + CELL_SIZE);
+    for close_island in kdtree
+        .0
+        .query_rect(min_x - 1, max_x + 1, min_y - 1, max_y + 1)
+    {
+        let island = match islands.0.get(&close_island.island_id) {
+            Some(island) => island,
+            None => continue,
+        };
+        //in the coordinates of the island mesh
+        let to_island = |tile_x: f32, tile_y: f32| {
+            Point::new(
+                (tile_x - island.min_x as f32) * TILE_SIZE as f32,
+                (tile_y - island.min_y as f32) * TILE_SIZE as f32,
+            )
+        };
+        let min = to_island(min_x as f32 - CLEARANCE, min_y as f32 - CLEARANCE);
+        let max = to_island(max_x as f32 + CLEARANCE, max_y as f32 + CLEARANCE);
+        let touches = |collider: &Option<IslandCollider>| {
+            collider
+                .as_ref()
+                .map(|collider| collider.overlaps(min, max))
+                .unwrap_or(false)
+        };
+        if touches(&island.rigid_collider) || touches(&island.friction_collider) {
+            return true;
+        }
+    }
+    false
+}
+
+//The cells around the islands that changed are computed again, and the paths through them are forgotten.
+//The paths that found no way are all forgotten, the search went who knows where.
+fn invalidate_system(
+    mut navigation: ResMut<SeaNavigation>,
+    mut changed_reader: EventReader<IslandsChangedEvent>,
+) {
+    for event in changed_reader.iter() {
+        let margin = CLEARANCE.ceil() as i32 + 1;
+        let min = (
+            (event.min.0 - margin).div_euclid(CELL_SIZE),
+            (event.min.1 - margin).div_euclid(CELL_SIZE),
+        );
+        let max = (
+            (event.max.0 + margin).div_euclid(CELL_SIZE),
+            (event.max.1 + margin).div_euclid(CELL_SIZE),
+        );
+        navigation
+            .blocked
+            .retain(|cell, _| cell.0 < min.0 || cell.0 > max.0 || cell.1 < min.1 || cell.1 > max.1);
+        navigation.paths.retain(|_, cached| {
+            cached.path.is_some()
+                && (cached.max.0 < min.0
+                    || cached.min.0 > max.0
+                    || cached.max.1 < min.1
+                    || cached.min.1 > max.1)
+        });
+    }
+}
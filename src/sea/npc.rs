@@ -0,0 +1,720 @@
+use std::{
+    f32::consts::PI,
+    hash::{Hash, Hasher},
+};
+
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use seahash::SeaHasher;
+
+use crate::{
+    clock::WorldClock, land::shipyard::has_shipyard, loading::GameState, util::SeededHasher,
+};
+
+use super::{
+    collision::{
+        SeaCollider, SeaCollisionTree, SeaContacts, ShipHitIsland, SPAWN_DISTANCE_X,
+        SPAWN_DISTANCE_Y,
+    },
+    combat::{fire_broadside, spawn_debris, spawn_splash, Cannons, CombatMaterials, Side},
+    currents::Currents,
+    loader::ShipsConfig,
+    map::Islands,
+    minimap::MinimapIcon,
+    navigation::{Route, SeaNavigation},
+    player::{Player, PlayerPositionUpdate},
+    sailing::{wind_angle, ShipMotion},
+    ships::{DamageState, Hull, Ship},
+    weather::Weather,
+    wind::{optimal_trim, point_of_sail_efficiency, Wind},
+    worldgen::{Island, IslandGenerator, IslandId, WorldGenerator},
+    SeaPaused, ISLAND_SCALING, TILE_SIZE,
+};
+
+//the ships are placed by regions of that many tiles
+const REGION_SIZE: i32 = 64;
+const MAX_SHIPS_PER_REGION: u64 = 2;
+//one ship in PIRATE_RARITY is a pirate, the others are merchants
+const PIRATE_RARITY: u64 = 3;
+//tries to find open sea for a ship in its region
+const SPAWN_TRIES: u64 = 8;
+//no land this close to the spawn point of a ship, in tiles
+const SPAWN_CLEARANCE: i32 = 3;
+//the ships are culled a bit further than they spawn, in tiles
+const CULL_MARGIN: i32 = 20;
+
+//distances to the player, in world units
+const CHASE_DISTANCE: f32 = 800.;
+const ATTACK_DISTANCE: f32 = 250.;
+const FLEE_DISTANCE: f32 = 600.;
+//pirates flee under that part of their hull
+const FLEE_HULL: f32 = 0.3;
+//how far from home pirates patrol, and merchants look for ports
+const PATROL_DISTANCE: f32 = 400.;
+const TRADE_DISTANCE: f32 = 3000.;
+//a waypoint is reached that close
+const WAYPOINT_DISTANCE: f32 = 60.;
+//the ships can't sail closer to the wind than that efficiency, they tack instead
+const TACKING_EFFICIENCY: f32 = 0.3;
+const TACK_ANGLE: f32 = PI / 3.;
+const TACK_TIME: f32 = 8.;
+//the helm is left alone under that error of heading
+const HEADING_TOLERANCE: f32 = PI / 16.;
+//the cannons are fired when the target is in that angle from the side of the ship
+const FIRING_ANGLE: f32 = PI / 9.;
+//how long a ship rows back after hitting the coast, in seconds
+const BACKING_TIME: f32 = 1.5;
+
+pub struct SeaNpcPlugin;
+impl Plugin for SeaNpcPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<NpcShips>()
+            .on_state_exit(GameState::STAGE, GameState::Sea, unload_system.system())
+            .on_state_update(GameState::STAGE, GameState::Sea, spawn_system.system())
+            .on_state_update(GameState::STAGE, GameState::Sea, ai_system.system())
+            .on_state_update(GameState::STAGE, GameState::Sea, movement_system.system())
+            .on_state_update(GameState::STAGE, GameState::Sea, hull_system.system());
+    }
+}
+
+//A ship is identified by its region and its index in it, like the islands they only depend on the seed.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub struct NpcId {
+    region: (i32, i32),
+    index: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NpcKind {
+    Pirate,
+    Merchant,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Behavior {
+    Patrol,
+    Chase,
+    Attack,
+    Flee,
+    Trade,
+}
+
+struct NpcSpawn {
+    id: NpcId,
+    kind: NpcKind,
+    class: usize,
+    //in tiles
+    tile: (i32, i32),
+}
+
+//A ship out of range, it comes back where it was left.
+struct CulledShip {
+    npc: NpcShip,
+    ship: Ship,
+    motion: ShipMotion,
+    hull: Hull,
+    translation: Vec3,
+}
+
+#[derive(Default)]
+pub struct NpcShips {
+    regions: HashMap<(i32, i32), Vec<NpcSpawn>>,
+    spawned: HashMap<NpcId, Entity>,
+    //the ships sunk stay at the bottom of the sea
+    sunk: HashSet<NpcId>,
+    //the ships culled, by the region they were culled in, whatever region they come from
+    culled: HashMap<(i32, i32), Vec<CulledShip>>,
+    culled_in: HashMap<NpcId, (i32, i32)>,
+}
+
+impl NpcShips {
+    fn keep(&mut self, ship: CulledShip) {
+        let (x, y) = world_to_tile(ship.translation.truncate());
+        let region = (x.div_euclid(REGION_SIZE), y.div_euclid(REGION_SIZE));
+        self.culled_in.insert(ship.npc.id, region);
+        self.culled.entry(region).or_default().push(ship);
+    }
+}
+
+#[derive(Clone)]
+pub struct NpcShip {
+    pub id: NpcId,
+    pub kind: NpcKind,
+    pub behavior: Behavior,
+    home: Vec2,
+    waypoint: Option<Vec2>,
+    //the port a merchant sails to, and the last one it went to
+    port: Option<IslandId>,
+    last_port: Option<IslandId>,
+    //1 or -1, the side of the wind the ship tacks on
+    tack: f32,
+    tack_timer: Timer,
+    backing: Timer,
+    //to draw the waypoints of the patrol
+    waypoints_drawn: u64,
+    //the way around the islands to the port or the waypoint
+    route: Route,
+}
+
+//The ships of a region, from the seed.
+fn region_spawns(
+    mut hasher: SeaHasher,
+    generator: &IslandGenerator,
+    region: (i32, i32),
+    classes: usize,
+) -> Vec<NpcSpawn> {
+    hasher.write(b"ships");
+    region.hash(&mut hasher);
+    let count = hasher.finish() % (MAX_SHIPS_PER_REGION + 1);
+    let is_open_sea = |(x, y): (i32, i32)| {
+        (-SPAWN_CLEARANCE..=SPAWN_CLEARANCE).all(|i| {
+            (-SPAWN_CLEARANCE..=SPAWN_CLEARANCE).all(|j| !generator.is_land((x + i, y + j)))
+        })
+    };
+    (0..count)
+        .filter_map(|index| {
+            let mut ship_hasher = hasher.clone();
+            ship_hasher.write_u64(index);
+            let kind = if ship_hasher.finish() % PIRATE_RARITY == 0 {
+                NpcKind::Pirate
+            } else {
+                NpcKind::Merchant
+            };
+            ship_hasher.write(b"class");
+            let class = (ship_hasher.finish() % classes as u64) as usize;
+            let tile = (0..SPAWN_TRIES)
+                .map(|attempt| {
+                    ship_hasher.write_u64(attempt);
+                    let value = ship_hasher.finish();
+                    (
+                        region.0 * REGION_SIZE + (value % REGION_SIZE as u64) as i32,
+                        region.1 * REGION_SIZE + ((value >> 32) % REGION_SIZE as u64) as i32,
+                    )
+                })
+                .find(|tile| is_open_sea(*tile))?;
+            Some(NpcSpawn {
+                id: NpcId { region, index },
+                kind,
+                class,
+                tile,
+            })
+        })
+        .collect()
+}
+
+fn tile_to_world((x, y): (i32, i32)) -> Vec2 {
+    let tile_size = TILE_SIZE as f32 * ISLAND_SCALING;
+    Vec2::new((x as f32 + 0.5) * tile_size, (y as f32 + 0.5) * tile_size)
+}
+
+fn world_to_tile(position: Vec2) -> (i32, i32) {
+    let tile_size = TILE_SIZE as f32 * ISLAND_SCALING;
+    (
+        (position.x / tile_size).floor() as i32,
+        (position.y / tile_size).floor() as i32,
+    )
+}
+
+//The ships spawn where the islands spawn around the player, and are culled a bit further.
+//A culled ship comes back where it was culled, not at home.
+fn spawn_system(
+    commands: &mut Commands,
+    hasher: Res<SeededHasher>,
+    ships: Res<ShipsConfig>,
+    generator: Res<WorldGenerator>,
+    pos_update: Res<PlayerPositionUpdate>,
+    mut npcs: ResMut<NpcShips>,
+    npc_query: Query<(Entity, &NpcShip, &Ship, &ShipMotion, &Hull, &Transform)>,
+) {
+    //no ship to sail without a class
+    if ships.0.is_empty() {
+        return;
+    }
+    let npcs = &mut *npcs;
+    let generator = match &generator.0 {
+        Some(generator) => generator,
+        None => return,
+    };
+    let (x, y) = (pos_update.x, pos_update.y);
+    for (entity, npc, ship, motion, hull, transform) in npc_query.iter() {
+        let (ship_x, ship_y) = world_to_tile(transform.translation.truncate());
+        if (ship_x - x).abs() > SPAWN_DISTANCE_X + CULL_MARGIN
+            || (ship_y - y).abs() > SPAWN_DISTANCE_Y + CULL_MARGIN
+        {
+            npcs.spawned.remove(&npc.id);
+            npcs.keep(cull(npc, ship, motion, hull, transform));
+            commands.despawn_recursive(entity);
+        }
+    }
+    let min_region = (
+        (x - SPAWN_DISTANCE_X).div_euclid(REGION_SIZE),
+        (y - SPAWN_DISTANCE_Y).div_euclid(REGION_SIZE),
+    );
+    let max_region = (
+        (x + SPAWN_DISTANCE_X).div_euclid(REGION_SIZE),
+        (y + SPAWN_DISTANCE_Y).div_euclid(REGION_SIZE),
+    );
+    let is_near = |region: &(i32, i32)| {
+        region.0 >= min_region.0 - 2
+            && region.0 <= max_region.0 + 2
+            && region.1 >= min_region.1 - 2
+            && region.1 <= max_region.1 + 2
+    };
+    //the regions far away are forgotten, they are quick to compute again
+    npcs.regions.retain(|region, _| is_near(region));
+    //the ships culled far away are forgotten too, they are back home when the player comes back
+    let NpcShips {
+        culled, culled_in, ..
+    } = &mut *npcs;
+    culled.retain(|region, _| is_near(region));
+    culled_in.retain(|_, region| is_near(region));
+    let in_range = |(tile_x, tile_y): (i32, i32)| {
+        (tile_x - x).abs() <= SPAWN_DISTANCE_X && (tile_y - y).abs() <= SPAWN_DISTANCE_Y
+    };
+    for region_x in min_region.0..=max_region.0 {
+        for region_y in min_region.1..=max_region.1 {
+            let region = (region_x, region_y);
+            //the ships culled in the region
+            let mut returning = Vec::new();
+            if let Some(ships_culled) = npcs.culled.get_mut(&region) {
+                let (back, left) = ships_culled
+                    .drain(..)
+                    .partition(|ship| in_range(world_to_tile(ship.translation.truncate())));
+                *ships_culled = left;
+                returning = back;
+            }
+            for ship in returning {
+                npcs.culled_in.remove(&ship.npc.id);
+                let id = ship.npc.id;
+                let entity = spawn_ship(commands, &ships, ship);
+                npcs.spawned.insert(id, entity);
+            }
+            //the ships at home in the region
+            let NpcShips {
+                regions,
+                spawned,
+                sunk,
+                culled_in,
+                ..
+            } = &mut *npcs;
+            let spawns = regions.entry(region).or_insert_with(|| {
+                region_spawns(hasher.get_hasher(), generator, region, ships.0.len())
+            });
+            for spawn in spawns.iter() {
+                if spawned.contains_key(&spawn.id)
+                    || sunk.contains(&spawn.id)
+                    || culled_in.contains_key(&spawn.id)
+                    || !in_range(spawn.tile)
+                {
+                    continue;
+                }
+                let (_, class) = &ships.0[spawn.class];
+                let home = tile_to_world(spawn.tile);
+                let mut backing = Timer::from_seconds(BACKING_TIME, false);
+                backing.tick(BACKING_TIME);
+                let ship = CulledShip {
+                    npc: NpcShip {
+                        id: spawn.id,
+                        kind: spawn.kind,
+                        behavior: match spawn.kind {
+                            NpcKind::Pirate => Behavior::Patrol,
+                            NpcKind::Merchant => Behavior::Trade,
+                        },
+                        home,
+                        waypoint: None,
+                        port: None,
+                        last_port: None,
+                        tack: 1.,
+                        tack_timer: Timer::from_seconds(TACK_TIME, true),
+                        backing,
+                        waypoints_drawn: 0,
+                        route: Route::default(),
+                    },
+                    ship: Ship {
+                        class: class.name.clone(),
+                    },
+                    motion: ShipMotion::default(),
+                    hull: Hull::new(class),
+                    translation: home.extend(99.),
+                };
+                let entity = spawn_ship(commands, &ships, ship);
+                spawned.insert(spawn.id, entity);
+            }
+        }
+    }
+}
+
+fn spawn_ship(commands: &mut Commands, ships: &ShipsConfig, ship: CulledShip) -> Entity {
+    let (assets, class) = ships.get(&ship.ship.class);
+    let kind = ship.npc.kind;
+    commands
+        .spawn(SpriteSheetBundle {
+            texture_atlas: assets.sheet.clone(),
+            transform: Transform::from_translation(ship.translation),
+            ..Default::default()
+        })
+        .with(ship.npc)
+        .with(ship.motion)
+        .with(ship.ship)
+        .with(ship.hull)
+        .with(Cannons::new(class))
+        .with(SeaCollider::ship())
+        .with(SeaContacts::default())
+        .with(MinimapIcon(match kind {
+            NpcKind::Pirate => Color::RED,
+            NpcKind::Merchant => Color::YELLOW,
+        }))
+        .current_entity()
+        .unwrap()
+}
+
+//the angle in (-PI, PI]
+fn wrap_angle(angle: f32) -> f32 {
+    let angle = angle.rem_euclid(2. * PI);
+    if angle > PI {
+        angle - 2. * PI
+    } else {
+        angle
+    }
+}
+
+fn direction_angle(direction: Vec2) -> f32 {
+    direction.y.atan2(direction.x)
+}
+
+fn island_center(island: &Island) -> Vec2 {
+    let tile_size = TILE_SIZE as f32 * ISLAND_SCALING;
+    Vec2::new(
+        (island.min_x + island.max_x) as f32 / 2.,
+        (island.min_y + island.max_y) as f32 / 2.,
+    ) * tile_size
+}
+
+//The next port of a merchant: the ports around its home are visited in turn.
+fn next_port(
+    hasher: &SeededHasher,
+    islands: &Islands,
+    home: Vec2,
+    last_port: Option<IslandId>,
+) -> Option<(IslandId, Vec2)> {
+    let mut ports: Vec<(IslandId, Vec2)> = islands
+        .0
+        .values()
+        .filter(|island| has_shipyard(hasher.get_hasher(), island.id))
+        .map(|island| (island.id, island_center(island)))
+        .filter(|(_, center)| (*center - home).length() < TRADE_DISTANCE)
+        .collect();
+    ports.sort_by_key(|(id, _)| id.0);
+    let next = match last_port {
+        Some(last) => ports.iter().position(|(id, _)| id.0 > last.0).unwrap_or(0),
+        None => 0,
+    };
+    //a lone port is no route, the merchant waits there
+    ports
+        .get(next)
+        .copied()
+        .filter(|(id, _)| Some(*id) != last_port)
+}
+
+//Chooses what the ships do, and sets their helm and sails.
+fn ai_system(
+    commands: &mut Commands,
+    time: Res<Time>,
+    hasher: Res<SeededHasher>,
+    wind: Res<Wind>,
+    weather: Res<Weather>,
+    clock: Res<WorldClock>,
+    ships: Res<ShipsConfig>,
+    islands: Res<Islands>,
+    kdtree: Res<SeaCollisionTree>,
+    mut navigation: ResMut<SeaNavigation>,
+    materials: Res<CombatMaterials>,
+    mut hit_reader: EventReader<ShipHitIsland>,
+    player_query: Query<(&Transform, &ShipMotion, &Hull), With<Player>>,
+    mut npc_query: Query<
+        (
+            Entity,
+            &mut NpcShip,
+            &mut ShipMotion,
+            &Ship,
+            &Hull,
+            &Transform,
+            &SeaContacts,
+            &mut Cannons,
+        ),
+        Without<Player>,
+    >,
+    paused: Res<SeaPaused>,
+) {
+    if paused.0 {
+        return;
+    }
+    //backs off the coast after hitting it
+    for hit in hit_reader.iter() {
+        if let Ok(mut npc) = npc_query.get_component_mut::<NpcShip>(hit.entity) {
+            if npc.backing.finished() {
+                npc.backing.reset();
+            }
+        }
+    }
+    let player = player_query
+        .iter()
+        .next()
+        .filter(|(_, _, hull)| hull.points > 0.)
+        .map(|(transform, motion, _)| (transform.translation.truncate(), motion.velocity()));
+    for (entity, mut npc, mut motion, ship, hull, transform, contacts, mut cannons) in
+        npc_query.iter_mut()
+    {
+        let class = &ships.get(&ship.class).1;
+        let position = transform.translation.truncate();
+        let to_player = player.map(|(player_position, _)| player_position - position);
+        let player_distance = to_player.map(|v| v.length()).unwrap_or(f32::MAX);
+        //pirates spot the player from closer at night and in bad weather
+        let chase_distance = CHASE_DISTANCE * clock.visibility() * weather.sight(position);
+        npc.behavior = match npc.kind {
+            NpcKind::Pirate if hull.points < hull.max * FLEE_HULL => Behavior::Flee,
+            NpcKind::Pirate if player_distance < ATTACK_DISTANCE => Behavior::Attack,
+            NpcKind::Pirate if player_distance < chase_distance => Behavior::Chase,
+            NpcKind::Pirate => Behavior::Patrol,
+            //a merchant that was shot at runs away from the player
+            NpcKind::Merchant if hull.points < hull.max && player_distance < FLEE_DISTANCE => {
+                Behavior::Flee
+            }
+            NpcKind::Merchant => Behavior::Trade,
+        };
+
+        let target = match (npc.behavior, player, to_player) {
+            (Behavior::Chase, Some((player_position, player_velocity)), _) => {
+                //aims where the player will be
+                Some(player_position + player_velocity)
+            }
+            (Behavior::Attack, _, Some(to_player)) => {
+                //turns to get the player abeam, on the closest side
+                let heading = motion.heading();
+                let side = if Side::Port.direction(heading).dot(to_player)
+                    > Side::Starboard.direction(heading).dot(to_player)
+                {
+                    Side::Port
+                } else {
+                    Side::Starboard
+                };
+                let direction = to_player.normalize();
+                let aim = side
+                    .direction(heading)
+                    .dot(direction)
+                    .max(-1.)
+                    .min(1.)
+                    .acos();
+                if aim < FIRING_ANGLE {
+                    fire_broadside(
+                        commands,
+                        &materials,
+                        entity,
+                        position,
+                        heading,
+                        motion.velocity(),
+                        class,
+                        &mut cannons,
+                        side,
+                    );
+                }
+                let along = match side {
+                    Side::Port => Vec2::new(direction.y, -direction.x),
+                    Side::Starboard => Vec2::new(-direction.y, direction.x),
+                };
+                Some(position + along * WAYPOINT_DISTANCE * 2.)
+            }
+            (Behavior::Flee, _, Some(to_player)) => Some(position - to_player),
+            (Behavior::Trade, _, _) => {
+                let port = npc
+                    .port
+                    .and_then(|id| islands.0.get(&id).map(|island| (id, island_center(island))));
+                let arrived = match port {
+                    Some((id, center)) => {
+                        contacts.island.island_id == Some(id)
+                            || (center - position).length() < WAYPOINT_DISTANCE
+                    }
+                    None => true,
+                };
+                //merchants stay in port for the night
+                if arrived && clock.is_night() {
+                    if let Some((id, _)) = port {
+                        npc.last_port = Some(id);
+                        npc.port = None;
+                    }
+                    None
+                } else if arrived {
+                    if let Some((id, _)) = port {
+                        npc.last_port = Some(id);
+                    }
+                    let next = next_port(&hasher, &islands, npc.home, npc.last_port);
+                    npc.port = next.map(|(id, _)| id);
+                    next.map(|(_, center)| center)
+                } else {
+                    port.map(|(_, center)| center)
+                }
+            }
+            _ => {
+                //patrols around home
+                let reached = npc
+                    .waypoint
+                    .map(|waypoint| (waypoint - position).length() < WAYPOINT_DISTANCE)
+                    .unwrap_or(true);
+                if reached {
+                    let mut waypoint_hasher = hasher.get_hasher();
+                    npc.id.hash(&mut waypoint_hasher);
+                    waypoint_hasher.write_u64(npc.waypoints_drawn);
+                    npc.waypoints_drawn += 1;
+                    let value = waypoint_hasher.finish();
+                    let angle = (value % 360) as f32 / 360. * 2. * PI;
+                    let distance = ((value >> 16) % 1000) as f32 / 1000. * PATROL_DISTANCE;
+                    npc.waypoint = Some(npc.home + Vec2::new(angle.cos(), angle.sin()) * distance);
+                }
+                npc.waypoint
+            }
+        };
+        //sails around the islands on the way to a port or a waypoint
+        let target = match npc.behavior {
+            Behavior::Trade | Behavior::Patrol => target.and_then(|target| {
+                navigation.follow(&mut npc.route, &islands, &kdtree, position, target)
+            }),
+            _ => target,
+        };
+
+        npc.backing.tick(time.delta_seconds());
+        if !npc.backing.finished() {
+            motion.sail = 0.;
+            motion.rowing = true;
+            motion.rotation_acceleration = 0.;
+            continue;
+        }
+        motion.rowing = false;
+
+        let target = match target {
+            Some(target) if (target - position).length() > f32::EPSILON => target,
+            _ => {
+                motion.sail = 0.;
+                motion.rotation_acceleration = 0.;
+                continue;
+            }
+        };
+        let wind_here = weather.wind(&wind, position);
+        let mut desired = direction_angle(target - position);
+        //can't sail against the wind, it tacks
+        let against_wind = wind_angle(desired, wind_here);
+        if point_of_sail_efficiency(against_wind) < TACKING_EFFICIENCY {
+            npc.tack_timer.tick(time.delta_seconds());
+            if npc.tack_timer.just_finished() {
+                npc.tack = -npc.tack;
+            }
+            desired = direction_angle(-wind_here) + npc.tack * TACK_ANGLE;
+        }
+        let error = wrap_angle(desired - motion.rotation);
+        motion.rotation_acceleration = if error.abs() < HEADING_TOLERANCE {
+            0.
+        } else {
+            error.signum() * class.handling.rotation_acceleration
+        };
+        motion.sail = if npc.behavior == Behavior::Attack {
+            0.5
+        } else {
+            1.
+        };
+        motion.trim = optimal_trim(wind_angle(motion.rotation, wind_here));
+    }
+}
+
+//The ships move like the player, and collide with the islands.
+fn movement_system(
+    time: Res<Time>,
+    wind: Res<Wind>,
+    weather: Res<Weather>,
+    currents: Res<Currents>,
+    ships: Res<ShipsConfig>,
+    mut npc_query: Query<
+        (
+            &mut ShipMotion,
+            &Ship,
+            &SeaContacts,
+            &mut Hull,
+            &mut Transform,
+            &mut TextureAtlasSprite,
+        ),
+        With<NpcShip>,
+    >,
+    paused: Res<SeaPaused>,
+) {
+    if paused.0 {
+        return;
+    }
+    for (mut motion, ship, contacts, mut hull, mut transform, mut sprite) in npc_query.iter_mut() {
+        let class = &ships.get(&ship.class).1;
+        sprite.index = motion.sprite_index();
+        let position = transform.translation.truncate();
+        motion.catch_wind(&class.handling, weather.wind(&wind, position));
+        motion.update(
+            &class.handling,
+            &contacts.island,
+            currents.sample(position) + weather.gust(position),
+            &mut hull,
+            &mut transform.translation,
+            time.delta_seconds(),
+        );
+    }
+}
+
+//Shows the damages of the ships, and sinks them.
+fn hull_system(
+    commands: &mut Commands,
+    materials: Res<CombatMaterials>,
+    mut npcs: ResMut<NpcShips>,
+    mut npc_query: Query<(Entity, &NpcShip, &Hull, &Transform, &mut TextureAtlasSprite)>,
+    paused: Res<SeaPaused>,
+) {
+    if paused.0 {
+        return;
+    }
+    for (entity, npc, hull, transform, mut sprite) in npc_query.iter_mut() {
+        let state = hull.state();
+        sprite.color = state.tint();
+        if state == DamageState::Sunk {
+            npcs.spawned.remove(&npc.id);
+            npcs.sunk.insert(npc.id);
+            spawn_splash(commands, &materials, transform.translation.truncate(), true);
+            spawn_debris(commands, &materials, transform.translation.truncate());
+            commands.despawn_recursive(entity);
+        }
+    }
+}
+
+//A snapshot of the ship, its state and where it is, to spawn it again as it was.
+fn cull(
+    npc: &NpcShip,
+    ship: &Ship,
+    motion: &ShipMotion,
+    hull: &Hull,
+    transform: &Transform,
+) -> CulledShip {
+    CulledShip {
+        npc: npc.clone(),
+        ship: ship.clone(),
+        motion: motion.clone(),
+        hull: hull.clone(),
+        translation: transform.translation,
+    }
+}
+
+//The ships are kept as they are, they come back when the player sails again.
+fn unload_system(
+    commands: &mut Commands,
+    mut npcs: ResMut<NpcShips>,
+    npc_query: Query<(Entity, &NpcShip, &Ship, &ShipMotion, &Hull, &Transform)>,
+) {
+    npcs.spawned.clear();
+    for (entity, npc, ship, motion, hull, transform) in npc_query.iter() {
+        npcs.keep(cull(npc, ship, motion, hull, transform));
+        commands.despawn_recursive(entity);
+    }
+}
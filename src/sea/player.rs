@@ -1,11 +1,21 @@
 use bevy::{prelude::*, render::camera::Camera};
 use parry2d::{math::Vector, na::Unit};
-
-use std::f32::consts::PI;
+use serde::{Deserialize, Serialize};
 
 use crate::loading::GameState;
 
-use super::{loader::SeaHandles, ISLAND_SCALING, TILE_SIZE};
+use super::{
+    collision::{SeaCollider, SeaContacts},
+    combat::{spawn_debris, Cannons, CombatMaterials},
+    currents::Currents,
+    loader::ShipsConfig,
+    sailing::ShipMotion,
+    ships::{DamageState, Hull, Ship},
+    weather::Weather,
+    wind::Wind,
+    worldgen::IslandId,
+    SeaPaused, ISLAND_SCALING, TILE_SIZE,
+};
 pub struct SeaPlayerPlugin;
 impl Plugin for SeaPlayerPlugin {
     fn build(&self, app: &mut AppBuilder) {
@@ -23,46 +33,42 @@ impl Plugin for SeaPlayerPlugin {
                 GameState::STAGE,
                 GameState::Sea,
                 player_orientation.system(),
-            );
+            )
+            .on_state_update(GameState::STAGE, GameState::Sea, sailing_system.system())
+            .on_state_update(GameState::STAGE, GameState::Sea, hull_system.system())
+            .on_state_update(GameState::STAGE, GameState::Sea, sinking_system.system())
+            .add_event::<ShipSunkEvent>();
     }
 }
 
-struct PlayerSave {
-    translation: Vec3,
-    player: Player,
+pub struct PlayerSave {
+    pub translation: Vec3,
+    pub motion: ShipMotion,
+    pub ship: Ship,
+    //None for a new ship, its hull is whole
+    pub hull: Option<Hull>,
 }
 impl Default for PlayerSave {
     fn default() -> Self {
         Self {
             translation: Vec3::new(0., 0., 100.),
-            player: Player::default(),
+            motion: ShipMotion::default(),
+            ship: Ship::default(),
+            hull: None,
         }
     }
 }
-#[derive(Clone)]
-pub struct Player {
-    rotation: f32,
-    rotation_speed: f32,
-    rotation_acceleration: f32,
-    speed: f32,
-    acceleration: f32,
-    friction: f32,
-    rotation_friction: f32,
-}
-impl Default for Player {
-    fn default() -> Player {
-        Player {
-            speed: 0.,
-            acceleration: 0.,
-            rotation: 0.,
-            rotation_speed: 0.,
-            rotation_acceleration: 0.,
-            friction: 0.2,
-            rotation_friction: 10.,
-        }
-    }
+
+//the ship goes down, then the player starts again at the beginning with a new ship
+struct Sinking {
+    timer: Timer,
 }
+const SINKING_TIME: f32 = 3.;
+pub struct ShipSunkEvent;
+//the ship sailed by the player
+pub struct Player;
 
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub enum CollisionType {
     None,
     Friction,
@@ -75,8 +81,10 @@ pub struct PlayerPositionUpdate {
     pub translation: Vec3,
     pub changed_tile: bool,
     pub collision_status: CollisionType,
-    pub island_id: Option<u32>,
+    pub island_id: Option<IslandId>,
     pub contact: Option<(f32, f32, Unit<Vector<f32>>)>,
+    //how deep the ship is in the island, in island units
+    pub penetration: f32,
     pub sprite_id: u32,
 }
 impl PlayerPositionUpdate {
@@ -98,40 +106,64 @@ impl Default for PlayerPositionUpdate {
             changed_tile: true,
             collision_status: CollisionType::None,
             contact: None,
+            penetration: 0.,
         }
     }
 }
 
-fn load_system(commands: &mut Commands, handles: Res<SeaHandles>, save: Res<PlayerSave>) {
+fn load_system(commands: &mut Commands, ships: Res<ShipsConfig>, save: Res<PlayerSave>) {
     commands
         .spawn(SpriteSheetBundle {
-            texture_atlas: handles.boat.clone(),
+            texture_atlas: ships.get(&save.ship.class).0.sheet.clone(),
             transform: Transform::from_translation(save.translation),
             ..Default::default()
         })
-        .with(save.player.clone());
+        .with(Player)
+        .with(save.motion.clone())
+        .with(save.ship.clone())
+        .with(Cannons::new(&ships.get(&save.ship.class).1))
+        .with(SeaCollider::ship())
+        .with(SeaContacts::default())
+        .with(
+            save.hull
+                .clone()
+                .unwrap_or_else(|| Hull::new(&ships.get(&save.ship.class).1)),
+        );
 }
 
 fn unload_system(
     commands: &mut Commands,
     mut save: ResMut<PlayerSave>,
-    player_query: Query<(Entity, &Transform, &Player)>,
+    player_query: Query<(Entity, &Transform, &ShipMotion, &Ship, &Hull), With<Player>>,
 ) {
-    for (entity, transform, player) in player_query.iter() {
+    for (entity, transform, motion, ship, hull) in player_query.iter() {
         save.translation = transform.translation;
-        save.player = player.clone();
+        save.motion = motion.clone();
+        save.ship = ship.clone();
+        save.hull = Some(hull.clone());
         commands.despawn_recursive(entity);
     }
 }
 
+//the sails are set or furled by steps
+const SAIL_STEP: f32 = 0.5;
+//how fast the sheets are hauled or eased, per second
+const TRIM_SPEED: f32 = 0.5;
+
 fn keyboard_input_system(
+    time: Res<Time>,
     keyboard_input: Res<Input<KeyCode>>,
-    mut player_query: Query<&mut Player>,
+    ships: Res<ShipsConfig>,
+    mut player_query: Query<(&mut ShipMotion, &Ship), (With<Player>, Without<Sinking>)>,
+    paused: Res<SeaPaused>,
 ) {
-    for mut player in player_query.iter_mut() {
-        if keyboard_input.just_released(KeyCode::Up) || keyboard_input.just_released(KeyCode::Down)
-        {
-            player.acceleration = 0.;
+    if paused.0 {
+        return;
+    }
+    for (mut player, ship) in player_query.iter_mut() {
+        let handling = &ships.get(&ship.class).1.handling;
+        if keyboard_input.just_released(KeyCode::Down) {
+            player.rowing = false;
         }
 
         if keyboard_input.just_released(KeyCode::Right)
@@ -141,67 +173,60 @@ fn keyboard_input_system(
         }
 
         if keyboard_input.just_pressed(KeyCode::Up) {
-            player.acceleration = 100.;
+            player.sail = (player.sail + SAIL_STEP).min(1.);
         } else if keyboard_input.just_pressed(KeyCode::Down) {
-            player.acceleration = -100.;
+            player.rowing = player.sail <= 0.;
+            player.sail = (player.sail - SAIL_STEP).max(0.);
+        }
+
+        if keyboard_input.pressed(KeyCode::W) {
+            player.trim = (player.trim + TRIM_SPEED * time.delta_seconds()).min(1.);
+        } else if keyboard_input.pressed(KeyCode::S) {
+            player.trim = (player.trim - TRIM_SPEED * time.delta_seconds()).max(0.);
         }
 
         if keyboard_input.just_pressed(KeyCode::Right) {
-            player.rotation_acceleration = -20.;
+            player.rotation_acceleration = -handling.rotation_acceleration;
         } else if keyboard_input.just_pressed(KeyCode::Left) {
-            player.rotation_acceleration = 20.;
+            player.rotation_acceleration = handling.rotation_acceleration;
         }
     }
 }
 
 fn player_movement(
     time: Res<Time>,
-    mut stuck_forward: Local<Option<bool>>,
+    currents: Res<Currents>,
+    weather: Res<Weather>,
+    ships: Res<ShipsConfig>,
     mut pos_update: ResMut<PlayerPositionUpdate>,
-    mut player_query: Query<(&mut Player, &mut Transform)>,
+    mut player_query: Query<
+        (
+            &mut ShipMotion,
+            &Ship,
+            &SeaContacts,
+            &mut Hull,
+            &mut Transform,
+        ),
+        With<Player>,
+    >,
     mut camera_query: Query<(&Camera, &mut Transform)>,
+    paused: Res<SeaPaused>,
 ) {
-    for (mut player, mut player_transform) in player_query.iter_mut() {
-        player.rotation_speed += (player.rotation_acceleration
-            - player.rotation_speed * player.rotation_friction)
-            * time.delta_seconds();
-
-        let rounded_angle = (0.5 + 8. * player.rotation / (2. * PI)).floor() / 8.0 * (2. * PI);
-        let (s, c) = f32::sin_cos(rounded_angle);
-        match pos_update.collision_status {
-            CollisionType::None => {
-                *stuck_forward = None;
-                player.rotation =
-                    (player.rotation + player.rotation_speed * time.delta_seconds()) % (2. * PI);
-                player.speed += (player.acceleration
-                    - (1. + player.rotation_speed.abs()) * player.speed * player.friction)
-                    * time.delta_seconds();
-                player_transform.translation.x += c * player.speed * time.delta_seconds();
-                player_transform.translation.y += s * player.speed * time.delta_seconds();
-            }
-            CollisionType::Friction => {
-                *stuck_forward = None;
-                player.speed += (player.acceleration - player.speed * player.friction * 20.)
-                    * time.delta_seconds();
-                player_transform.translation.x += c * player.speed * time.delta_seconds();
-                player_transform.translation.y += s * player.speed * time.delta_seconds();
-            }
-            CollisionType::Rigid => {
-                player.speed += (player.acceleration - player.speed * player.friction * 20.)
-                    * time.delta_seconds();
-                if stuck_forward.is_none() {
-                    *stuck_forward = Some(player.speed > 0.)
-                }
-                if (stuck_forward.unwrap() && player.speed < 0.)
-                    || (!stuck_forward.unwrap() && player.speed > 0.)
-                {
-                    player_transform.translation.x += c * player.speed * time.delta_seconds();
-                    player_transform.translation.y += s * player.speed * time.delta_seconds();
-                } else {
-                    player.speed = 0.;
-                }
-            }
-        }
+    if paused.0 {
+        return;
+    }
+    for (mut motion, ship, contacts, mut hull, mut player_transform) in player_query.iter_mut() {
+        let position = player_transform.translation.truncate();
+        //the gusts of the storms push the ship like the currents
+        let current = currents.sample(position) + weather.gust(position);
+        motion.update(
+            &ships.get(&ship.class).1.handling,
+            &contacts.island,
+            current,
+            &mut hull,
+            &mut player_transform.translation,
+            time.delta_seconds(),
+        );
         pos_update.update(&player_transform.translation);
         for (_camera, mut camera_transform) in camera_query.iter_mut() {
             camera_transform.translation.x = player_transform.translation.x;
@@ -210,14 +235,115 @@ fn player_movement(
     }
 }
 
+fn sailing_system(
+    wind: Res<Wind>,
+    weather: Res<Weather>,
+    ships: Res<ShipsConfig>,
+    mut player_query: Query<(&mut ShipMotion, &Ship, &Transform), With<Player>>,
+    paused: Res<SeaPaused>,
+) {
+    if paused.0 {
+        return;
+    }
+    for (mut motion, ship, transform) in player_query.iter_mut() {
+        let wind_here = weather.wind(&wind, transform.translation.truncate());
+        motion.catch_wind(&ships.get(&ship.class).1.handling, wind_here);
+    }
+}
+
+//Shows the damages of the hull, and starts sinking when it breaks.
+fn hull_system(
+    commands: &mut Commands,
+    materials: Res<CombatMaterials>,
+    mut player_query: Query<
+        (
+            Entity,
+            &mut ShipMotion,
+            &Hull,
+            &Transform,
+            &mut TextureAtlasSprite,
+        ),
+        (With<Player>, Without<Sinking>),
+    >,
+    paused: Res<SeaPaused>,
+) {
+    if paused.0 {
+        return;
+    }
+    for (entity, mut player, hull, transform, mut sprite) in player_query.iter_mut() {
+        let state = hull.state();
+        sprite.color = state.tint();
+        if state == DamageState::Sunk {
+            player.sail = 0.;
+            player.rowing = false;
+            player.rotation_acceleration = 0.;
+            spawn_debris(commands, &materials, transform.translation.truncate());
+            commands.insert_one(
+                entity,
+                Sinking {
+                    timer: Timer::from_seconds(SINKING_TIME, false),
+                },
+            );
+        }
+    }
+}
+
+//The ship fades under the waves, then the player gets a new ship at the starting point.
+fn sinking_system(
+    commands: &mut Commands,
+    time: Res<Time>,
+    ships: Res<ShipsConfig>,
+    mut sunk_events: ResMut<Events<ShipSunkEvent>>,
+    mut player_query: Query<(
+        Entity,
+        &mut Sinking,
+        &mut ShipMotion,
+        &mut Ship,
+        &mut Hull,
+        &mut Transform,
+        &mut TextureAtlasSprite,
+        &mut Handle<TextureAtlas>,
+    )>,
+    paused: Res<SeaPaused>,
+) {
+    if paused.0 {
+        return;
+    }
+    for (
+        entity,
+        mut sinking,
+        mut player,
+        mut ship,
+        mut hull,
+        mut transform,
+        mut sprite,
+        mut atlas,
+    ) in player_query.iter_mut()
+    {
+        sinking.timer.tick(time.delta_seconds());
+        let left = 1. - sinking.timer.percent();
+        sprite.color.set_a(left);
+        transform.scale = Vec3::new(0.5 + 0.5 * left, 0.5 + 0.5 * left, 1.);
+        if sinking.timer.finished() {
+            let save = PlayerSave::default();
+            *player = save.motion;
+            *ship = save.ship;
+            *hull = Hull::new(&ships.get(&ship.class).1);
+            *atlas = ships.get(&ship.class).0.sheet.clone();
+            *transform = Transform::from_translation(save.translation);
+            sprite.color = DamageState::Intact.tint();
+            commands.remove_one::<Sinking>(entity);
+            sunk_events.send(ShipSunkEvent);
+        }
+    }
+}
+
 fn player_orientation(
-    mut player_query: Query<(&Player, &mut TextureAtlasSprite)>,
+    mut player_query: Query<(&ShipMotion, &mut TextureAtlasSprite), With<Player>>,
     mut player_pos_update: ResMut<PlayerPositionUpdate>,
 ) {
-    for (player, mut sprite) in player_query.iter_mut() {
-        sprite.index = (((0.5 - 8. * player.rotation / (2. * std::f32::consts::PI)).floor() as i32
-            + 21)
-            % 8) as u32;
+    for (motion, mut sprite) in player_query.iter_mut() {
+        sprite.index = motion.sprite_index();
         player_pos_update.sprite_id = sprite.index;
     }
 }
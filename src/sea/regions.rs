@@ -0,0 +1,79 @@
+use std::{hash::Hasher, sync::Arc};
+
+use seahash::SeaHasher;
+
+use super::worldgen::{select_biome, Biome};
+
+//The world is split in voronoi cells, each one with its own biome.
+//There is one seed point per REGION_SIZE x REGION_SIZE square of tiles.
+pub const REGION_SIZE: i32 = 256;
+//Around the border between two regions the terrain of both biomes is blended over that many tiles.
+const BLEND_DISTANCE: f32 = 24.;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RegionSample {
+    //the biome of the region the tile is in
+    pub biome: usize,
+    //the biome of the closest other region
+    pub neighbour: usize,
+    //how much the neighbour biome weights, 0.5 on the border and 0 further than BLEND_DISTANCE
+    pub blend: f32,
+}
+
+pub struct BiomeRegions {
+    hasher: SeaHasher,
+    biomes: Arc<Vec<Biome>>,
+}
+
+impl BiomeRegions {
+    pub fn new(mut hasher: SeaHasher, biomes: Arc<Vec<Biome>>) -> Self {
+        hasher.write(b"biome_regions");
+        BiomeRegions { hasher, biomes }
+    }
+
+    //the seed point of a cell, and its biome
+    fn cell(&self, (cell_x, cell_y): (i32, i32)) -> ((f32, f32), usize) {
+        let mut hasher = self.hasher;
+        hasher.write_i32(cell_x);
+        hasher.write_i32(cell_y);
+        let hash = hasher.finish();
+        let offset_x = (hash & 0xFFFF) as f32 / 65535.;
+        let offset_y = ((hash >> 16) & 0xFFFF) as f32 / 65535.;
+        let point = (
+            (cell_x as f32 + offset_x) * REGION_SIZE as f32,
+            (cell_y as f32 + offset_y) * REGION_SIZE as f32,
+        );
+        (point, select_biome(hasher, &self.biomes))
+    }
+
+    pub fn sample(&self, (x, y): (i32, i32)) -> RegionSample {
+        let cell_x = x.div_euclid(REGION_SIZE);
+        let cell_y = y.div_euclid(REGION_SIZE);
+        let (x, y) = (x as f32, y as f32);
+        //the two closest seed points, the closest one first
+        let mut closest: [(f32, (f32, f32), usize); 2] =
+            [(f32::MAX, (0., 0.), 0), (f32::MAX, (0., 0.), 0)];
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                let (point, biome) = self.cell((cell_x + dx, cell_y + dy));
+                let dist = (point.0 - x).powi(2) + (point.1 - y).powi(2);
+                if dist < closest[0].0 {
+                    closest[1] = closest[0];
+                    closest[0] = (dist, point, biome);
+                } else if dist < closest[1].0 {
+                    closest[1] = (dist, point, biome);
+                }
+            }
+        }
+        let [(dist, point, biome), (other_dist, other_point, neighbour)] = closest;
+        //distance to the bisector of the two seed points, which is the border of the regions.
+        let between =
+            ((other_point.0 - point.0).powi(2) + (other_point.1 - point.1).powi(2)).sqrt();
+        let border_distance = (other_dist - dist) / (2. * between.max(1.));
+        RegionSample {
+            biome,
+            neighbour,
+            blend: 0.5 * (1. - border_distance / BLEND_DISTANCE).max(0.),
+        }
+    }
+}
@@ -0,0 +1,186 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+
+use super::{
+    collision::ShipContact,
+    player::CollisionType,
+    ships::{Handling, Hull},
+    wind::{optimal_trim, point_of_sail_efficiency},
+    ISLAND_SCALING,
+};
+
+//efficiency lost per unit of trim away from the best one
+const TRIM_PENALTY: f32 = 1.2;
+//part of the speed lost when hitting the coast head on, grazing it loses almost nothing
+const SLIDE_SPEED_LOSS: f32 = 0.5;
+//part of the velocity into the coast given back as a bounce
+const BOUNCE: f32 = 0.3;
+//how fast the bounce fades
+const DRIFT_FRICTION: f32 = 3.;
+//hitting the coast slower than that along its normal doesn't harm the hull
+const IMPACT_SPEED_THRESHOLD: f32 = 50.;
+//hull points lost per unit of impact speed above the threshold
+const IMPACT_DAMAGE: f32 = 0.15;
+//running aground on sand only harms the hull above that speed
+const GROUNDING_SPEED: f32 = 100.;
+//hull points lost per second and per unit of speed above the grounding speed
+const GROUNDING_DAMAGE: f32 = 0.05;
+
+//How a ship moves, and how it is sailed. The player and the other ships move the same way.
+#[derive(Clone)]
+pub struct ShipMotion {
+    pub rotation: f32,
+    rotation_speed: f32,
+    pub rotation_acceleration: f32,
+    speed: f32,
+    acceleration: f32,
+    //velocity that doesn't follow the heading, given by the coast when bouncing on it
+    drift: Vec2,
+    //how much canvas is set, from 0 (sails furled) to 1
+    pub sail: f32,
+    //how the sails are sheeted, from 0 (eased out) to 1 (sheeted in)
+    pub trim: f32,
+    //rowing backward, only possible with the sails furled
+    pub rowing: bool,
+}
+impl Default for ShipMotion {
+    fn default() -> ShipMotion {
+        ShipMotion {
+            speed: 0.,
+            acceleration: 0.,
+            rotation: 0.,
+            rotation_speed: 0.,
+            rotation_acceleration: 0.,
+            drift: Vec2::zero(),
+            sail: 0.,
+            trim: 0.5,
+            rowing: false,
+        }
+    }
+}
+
+impl ShipMotion {
+    pub fn heading(&self) -> Vec2 {
+        rounded_heading(self.rotation)
+    }
+
+    //the velocity of the ship, in world units per second, without the currents
+    pub fn velocity(&self) -> Vec2 {
+        self.heading() * self.speed + self.drift
+    }
+
+    //The wind pushes the ship depending on the angle between its heading and the wind, and on how the sails are set.
+    pub fn catch_wind(&mut self, handling: &Handling, wind: Vec2) {
+        if self.rowing {
+            self.acceleration = handling.rowing_force;
+            return;
+        }
+        let angle = wind_angle(self.rotation, wind);
+        let trim_factor = (1. - TRIM_PENALTY * (self.trim - optimal_trim(angle)).abs()).max(0.);
+        self.acceleration = handling.sail_force
+            * self.sail
+            * wind.length()
+            * point_of_sail_efficiency(angle)
+            * trim_factor;
+    }
+
+    //Moves the ship for a frame, given what it touches and the current of the sea under it.
+    pub fn update(
+        &mut self,
+        handling: &Handling,
+        contact: &ShipContact,
+        current: Vec2,
+        hull: &mut Hull,
+        translation: &mut Vec3,
+        delta_seconds: f32,
+    ) {
+        self.rotation_speed += (self.rotation_acceleration
+            - self.rotation_speed * handling.rotation_friction)
+            * delta_seconds;
+
+        let heading = rounded_heading(self.rotation);
+        let (c, s) = (heading.x, heading.y);
+        match contact.collision_status {
+            CollisionType::None => {
+                self.rotation = (self.rotation + self.rotation_speed * delta_seconds) % (2. * PI);
+                self.speed += (self.acceleration
+                    - (1. + self.rotation_speed.abs()) * self.speed * handling.friction)
+                    * delta_seconds;
+                translation.x += c * self.speed * delta_seconds;
+                translation.y += s * self.speed * delta_seconds;
+            }
+            CollisionType::Friction => {
+                self.speed +=
+                    (self.acceleration - self.speed * handling.friction * 20.) * delta_seconds;
+                let grounding = (self.speed.abs() - GROUNDING_SPEED).max(0.);
+                hull.damage(grounding * GROUNDING_DAMAGE * delta_seconds);
+                translation.x += c * self.speed * delta_seconds;
+                translation.y += s * self.speed * delta_seconds;
+            }
+            CollisionType::Rigid => {
+                self.speed +=
+                    (self.acceleration - self.speed * handling.friction * 20.) * delta_seconds;
+                match contact.contact {
+                    Some((_, _, normal)) => {
+                        let normal = Vec2::new(normal.x, normal.y);
+                        let velocity = heading * self.speed + self.drift;
+                        let into_coast = velocity.dot(normal);
+                        if into_coast < 0. {
+                            hull.damage(
+                                (-into_coast - IMPACT_SPEED_THRESHOLD).max(0.) * IMPACT_DAMAGE,
+                            );
+                            //the part going into the coast is removed, or bounced, and the ship slides along it
+                            let impact = -into_coast / velocity.length();
+                            let response = (velocity - normal * into_coast)
+                                * (1. - SLIDE_SPEED_LOSS * impact)
+                                - normal * into_coast * BOUNCE;
+                            self.speed = response.dot(heading);
+                            self.drift = response - heading * self.speed;
+                        }
+                        //gets the ship out of the island
+                        let push = normal * contact.penetration * ISLAND_SCALING;
+                        translation.x += push.x;
+                        translation.y += push.y;
+                    }
+                    None => self.speed = 0.,
+                }
+                translation.x += c * self.speed * delta_seconds;
+                translation.y += s * self.speed * delta_seconds;
+            }
+        }
+        //the currents carry the ship even when it doesn't move, but not into the coast
+        let mut current = current;
+        if let (CollisionType::Rigid, Some((_, _, normal))) =
+            (&contact.collision_status, contact.contact)
+        {
+            let normal = Vec2::new(normal.x, normal.y);
+            current -= normal * current.dot(normal).min(0.);
+        }
+        translation.x += current.x * delta_seconds;
+        translation.y += current.y * delta_seconds;
+        let drift = self.drift;
+        translation.x += drift.x * delta_seconds;
+        translation.y += drift.y * delta_seconds;
+        self.drift -= drift * (DRIFT_FRICTION * delta_seconds).min(1.);
+    }
+
+    //the index of the sprite of the ship, and of its collision mesh
+    pub fn sprite_index(&self) -> u32 {
+        (((0.5 - 8. * self.rotation / (2. * PI)).floor() as i32 + 21) % 8) as u32
+    }
+}
+
+//The ship sails in one of 8 directions, the rotation is rounded like the sprite.
+pub fn rounded_heading(rotation: f32) -> Vec2 {
+    let rounded_angle = (0.5 + 8. * rotation / (2. * PI)).floor() / 8.0 * (2. * PI);
+    let (s, c) = f32::sin_cos(rounded_angle);
+    Vec2::new(c, s)
+}
+
+//angle between the direction the wind blows to and the heading of the ship, 0 when the wind comes from behind
+pub fn wind_angle(rotation: f32, wind: Vec2) -> f32 {
+    let heading = rounded_heading(rotation);
+    let wind = wind.normalize();
+    heading.dot(wind).max(-1.).min(1.).acos()
+}
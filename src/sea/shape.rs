@@ -0,0 +1,201 @@
+use bevy::utils::HashMap;
+use parry2d::{
+    math::{Isometry, Point, Vector},
+    na::Unit,
+    shape::{Compound, SharedShape},
+};
+
+//The collision shape of the tiles of an island sharing a CollisionType.
+//The tiles are merged into as few rectangles as possible, so there is much less to test than with two triangles per tile,
+//and the outline of the tiles gives smooth normals along the coast.
+pub struct IslandCollider {
+    pub shape: Compound,
+    outline: Vec<OutlineSegment>,
+    //the segments of the outline by the tile they border, so a contact only looks at a few of them
+    tile_segments: HashMap<(i32, i32), Vec<usize>>,
+    tile_size: f32,
+}
+
+//the contact points are on the outline, the closest segment is at most that many tiles away
+const NORMAL_SEARCH_RADIUS: i32 = 1;
+
+//An edge between a tile of the shape and a tile outside of it, with the normals at both ends.
+struct OutlineSegment {
+    a: Point<f32>,
+    b: Point<f32>,
+    normal_a: Vector<f32>,
+    normal_b: Vector<f32>,
+}
+
+impl IslandCollider {
+    //mask is indexed as [x][y], the shape is in the coordinates of the island mesh.
+    //Returns None if no tile is in the mask.
+    pub fn new(mask: &[Vec<bool>], tile_size: f32) -> Option<Self> {
+        let rectangles = merge_rectangles(mask);
+        if rectangles.is_empty() {
+            return None;
+        }
+        let shapes = rectangles
+            .iter()
+            .map(|(x, y, width, height)| {
+                let half_width = *width as f32 * tile_size / 2.;
+                let half_height = *height as f32 * tile_size / 2.;
+                (
+                    Isometry::translation(
+                        *x as f32 * tile_size + half_width,
+                        *y as f32 * tile_size + half_height,
+                    ),
+                    SharedShape::cuboid(half_width, half_height),
+                )
+            })
+            .collect();
+        let (outline, tile_segments) = outline(mask, tile_size);
+        Some(IslandCollider {
+            shape: Compound::new(shapes),
+            outline,
+            tile_segments,
+            tile_size,
+        })
+    }
+
+    //Whether a rectangle, in the coordinates of the island mesh, touches the shape.
+    pub fn overlaps(&self, min: Point<f32>, max: Point<f32>) -> bool {
+        self.shape.shapes().iter().any(|(position, shape)| {
+            let aabb = shape.compute_aabb(position);
+            aabb.mins.x < max.x && aabb.maxs.x > min.x && aabb.mins.y < max.y && aabb.maxs.y > min.y
+        })
+    }
+
+    //The normal of the outline closest to the point, interpolated between the ends of the edge.
+    //Only the segments of the tiles around the point are looked at.
+    pub fn normal_at(&self, point: Point<f32>) -> Option<Unit<Vector<f32>>> {
+        let tile = (
+            (point.x / self.tile_size).floor() as i32,
+            (point.y / self.tile_size).floor() as i32,
+        );
+        let mut closest = None;
+        let mut closest_distance = f32::MAX;
+        let nearby = (-NORMAL_SEARCH_RADIUS..=NORMAL_SEARCH_RADIUS)
+            .flat_map(|dx| (-NORMAL_SEARCH_RADIUS..=NORMAL_SEARCH_RADIUS).map(move |dy| (dx, dy)))
+            .filter_map(|(dx, dy)| self.tile_segments.get(&(tile.0 + dx, tile.1 + dy)))
+            .flatten();
+        for segment in nearby.map(|index| &self.outline[*index]) {
+            let edge = segment.b - segment.a;
+            let t = ((point - segment.a).dot(&edge) / edge.norm_squared())
+                .max(0.)
+                .min(1.);
+            let distance = (segment.a + edge * t - point).norm_squared();
+            if distance < closest_distance {
+                closest_distance = distance;
+                closest = Some((segment, t));
+            }
+        }
+        let (segment, t) = closest?;
+        Unit::try_new(
+            segment.normal_a * (1. - t) + segment.normal_b * t,
+            f32::EPSILON,
+        )
+    }
+}
+
+fn is_set(mask: &[Vec<bool>], x: i32, y: i32) -> bool {
+    x >= 0
+        && y >= 0
+        && mask
+            .get(x as usize)
+            .and_then(|column| column.get(y as usize))
+            .copied()
+            .unwrap_or(false)
+}
+
+//Greedily covers the mask with rectangles (x, y, width, height): each one grows along y, then along x.
+fn merge_rectangles(mask: &[Vec<bool>]) -> Vec<(usize, usize, usize, usize)> {
+    let size_x = mask.len();
+    let size_y = mask.first().map(|v| v.len()).unwrap_or(0);
+    let mut used = vec![vec![false; size_y]; size_x];
+    let mut rectangles = Vec::new();
+    for x in 0..size_x {
+        for y in 0..size_y {
+            if !mask[x][y] || used[x][y] {
+                continue;
+            }
+            let mut height = 1;
+            while y + height < size_y && mask[x][y + height] && !used[x][y + height] {
+                height += 1;
+            }
+            let mut width = 1;
+            while x + width < size_x
+                && (y..y + height).all(|j| mask[x + width][j] && !used[x + width][j])
+            {
+                width += 1;
+            }
+            for column in used.iter_mut().skip(x).take(width) {
+                for cell in column.iter_mut().skip(y).take(height) {
+                    *cell = true;
+                }
+            }
+            rectangles.push((x, y, width, height));
+        }
+    }
+    rectangles
+}
+
+//The edges of the mask, the normal of a vertex is the mean of the normals of the edges touching it,
+//which rounds the corners of the coast. The edges are also indexed by the tile they border.
+fn outline(
+    mask: &[Vec<bool>],
+    tile_size: f32,
+) -> (Vec<OutlineSegment>, HashMap<(i32, i32), Vec<usize>>) {
+    //start, end and outward normal of every edge, in tiles
+    let mut edges = Vec::new();
+    let mut tile_segments: HashMap<(i32, i32), Vec<usize>> = HashMap::default();
+    for x in 0..mask.len() as i32 {
+        for y in 0..mask[x as usize].len() as i32 {
+            if !is_set(mask, x, y) {
+                continue;
+            }
+            let first_edge = edges.len();
+            if !is_set(mask, x + 1, y) {
+                edges.push(((x + 1, y), (x + 1, y + 1), (1., 0.)));
+            }
+            if !is_set(mask, x, y + 1) {
+                edges.push(((x + 1, y + 1), (x, y + 1), (0., 1.)));
+            }
+            if !is_set(mask, x - 1, y) {
+                edges.push(((x, y + 1), (x, y), (-1., 0.)));
+            }
+            if !is_set(mask, x, y - 1) {
+                edges.push(((x, y), (x + 1, y), (0., -1.)));
+            }
+            if edges.len() > first_edge {
+                tile_segments.insert((x, y), (first_edge..edges.len()).collect());
+            }
+        }
+    }
+    let mut vertex_normals: HashMap<(i32, i32), Vector<f32>> = HashMap::default();
+    for (a, b, (nx, ny)) in edges.iter() {
+        for vertex in [a, b].iter() {
+            *vertex_normals.entry(**vertex).or_insert_with(Vector::zeros) += Vector::new(*nx, *ny);
+        }
+    }
+    let point = |(x, y): (i32, i32)| Point::new(x as f32 * tile_size, y as f32 * tile_size);
+    let normal = |vertex: (i32, i32), edge_normal: Vector<f32>| {
+        //opposite edges cancel out where two tiles touch by a corner, the edge keeps its own normal there.
+        vertex_normals[&vertex]
+            .try_normalize(f32::EPSILON)
+            .unwrap_or(edge_normal)
+    };
+    let segments = edges
+        .iter()
+        .map(|(a, b, (nx, ny))| {
+            let edge_normal = Vector::new(*nx, *ny);
+            OutlineSegment {
+                a: point(*a),
+                b: point(*b),
+                normal_a: normal(*a, edge_normal),
+                normal_b: normal(*b, edge_normal),
+            }
+        })
+        .collect();
+    (segments, tile_segments)
+}
@@ -0,0 +1,89 @@
+use bevy::prelude::Color;
+use serde::{Deserialize, Serialize};
+
+use super::worldgen::SheetConfig;
+
+//A kind of ship, read from config/ships.ron. The first one is the ship the player starts with.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ShipClass {
+    pub name: String,
+    pub handling: Handling,
+    pub hull: u32,
+    pub cargo: u32,
+    //cannons on each side of the ship
+    pub cannons: u32,
+    //seconds to reload the cannons of a side
+    pub reload: f32,
+    //one sprite per direction, in the order of the rounded heading
+    pub sprite_sheet: SheetConfig,
+    //same layout as the sprites, the opaque pixels collide with the islands
+    pub collision_sheet: SheetConfig,
+}
+
+//How a ship class reacts to the helm and the wind.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Handling {
+    pub friction: f32,
+    pub rotation_friction: f32,
+    pub rotation_acceleration: f32,
+    //acceleration with all sails set, well trimmed, on a beam reach in the strongest wind
+    pub sail_force: f32,
+    pub rowing_force: f32,
+}
+
+//The ship of a captain, an instance of a ship class.
+//The class is kept by name, so a save still points to the same class when the config is reordered.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Ship {
+    pub class: String,
+}
+
+//What is left of the hull of a ship, it sinks at 0.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Hull {
+    pub points: f32,
+    pub max: f32,
+}
+impl Hull {
+    pub fn new(class: &ShipClass) -> Self {
+        Hull {
+            points: class.hull as f32,
+            max: class.hull as f32,
+        }
+    }
+
+    pub fn damage(&mut self, damage: f32) {
+        self.points = (self.points - damage).max(0.);
+    }
+
+    pub fn state(&self) -> DamageState {
+        let ratio = self.points / self.max;
+        if ratio <= 0. {
+            DamageState::Sunk
+        } else if ratio < 1. / 3. {
+            DamageState::Critical
+        } else if ratio < 2. / 3. {
+            DamageState::Damaged
+        } else {
+            DamageState::Intact
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DamageState {
+    Intact,
+    Damaged,
+    Critical,
+    Sunk,
+}
+impl DamageState {
+    //the sprite of the ship darkens as the hull breaks
+    pub fn tint(self) -> Color {
+        match self {
+            DamageState::Intact => Color::WHITE,
+            DamageState::Damaged => Color::rgb(0.85, 0.75, 0.7),
+            DamageState::Critical | DamageState::Sunk => Color::rgb(0.6, 0.4, 0.35),
+        }
+    }
+}
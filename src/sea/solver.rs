@@ -0,0 +1,478 @@
+use std::{cmp::Reverse, collections::BinaryHeap, hash::Hasher};
+
+use seahash::SeaHasher;
+
+use super::{
+    autotile::{Terrain, WangTile},
+    map::TileKind,
+};
+
+//corners of a wang tile
+const NE: usize = 0;
+const SE: usize = 1;
+const SW: usize = 2;
+const NW: usize = 3;
+
+//directions to the neighbours of a cell : north (y + 1), east (x + 1), south (y - 1), west (x - 1)
+const DIRECTIONS: [(i32, i32); 4] = [(0, 1), (1, 0), (0, -1), (-1, 0)];
+
+//A contradiction is fixed by resetting the cells around it, this bounds how many times it can happen.
+const MAX_RESETS_PER_CELL: usize = 4;
+
+//Wave function collapse over the sprites of a tileset.
+//Every cell starts with the sprites containing its terrain, then the cell with the least possibilities
+//is collapsed to one of them and the constraints are propagated to its neighbours.
+//The domains are bitsets, so a tileset can have at most 64 wang tiles.
+pub struct ConstraintSolver<'a> {
+    tiles: &'a [WangTile],
+    //compatible[t][d] is the set of tiles that can be placed next to t in the direction d
+    compatible: Vec<[u64; 4]>,
+}
+
+struct Grid {
+    size_x: usize,
+    size_y: usize,
+    domains: Vec<u64>,
+    collapsed: Vec<bool>,
+    heap: BinaryHeap<Reverse<(u32, u64, usize)>>,
+}
+impl Grid {
+    fn index(&self, x: usize, y: usize) -> usize {
+        x * self.size_y + y
+    }
+
+    fn coords(&self, index: usize) -> (usize, usize) {
+        (index / self.size_y, index % self.size_y)
+    }
+
+    fn neighbour(&self, index: usize, direction: usize) -> Option<usize> {
+        let (x, y) = self.coords(index);
+        let (dx, dy) = DIRECTIONS[direction];
+        let (nx, ny) = (x as i32 + dx, y as i32 + dy);
+        if nx < 0 || ny < 0 || nx >= self.size_x as i32 || ny >= self.size_y as i32 {
+            None
+        } else {
+            Some(self.index(nx as usize, ny as usize))
+        }
+    }
+}
+
+impl<'a> ConstraintSolver<'a> {
+    pub fn new(tiles: &'a [WangTile]) -> Option<Self> {
+        if tiles.is_empty() || tiles.len() > 64 {
+            return None;
+        }
+        let compatible = tiles
+            .iter()
+            .map(|tile| {
+                let mut masks = [0u64; 4];
+                for (other_id, other) in tiles.iter().enumerate() {
+                    let c = &tile.corners;
+                    let o = &other.corners;
+                    let fits = [
+                        o[SE] == c[NE] && o[SW] == c[NW],
+                        o[NW] == c[NE] && o[SW] == c[SE],
+                        o[NE] == c[SE] && o[NW] == c[SW],
+                        o[NE] == c[NW] && o[SE] == c[SW],
+                    ];
+                    for (mask, fit) in masks.iter_mut().zip(fits.iter()) {
+                        if *fit {
+                            *mask |= 1 << other_id;
+                        }
+                    }
+                }
+                masks
+            })
+            .collect();
+        Some(ConstraintSolver { tiles, compatible })
+    }
+
+    pub fn tile(&self, id: usize) -> &WangTile {
+        &self.tiles[id]
+    }
+
+    fn all_tiles(&self) -> u64 {
+        if self.tiles.len() == 64 {
+            u64::MAX
+        } else {
+            (1 << self.tiles.len()) - 1
+        }
+    }
+
+    //the tiles with the given terrain at the given corners
+    fn with_corners(&self, corners: &[usize], terrain: Terrain) -> u64 {
+        self.tiles
+            .iter()
+            .enumerate()
+            .filter(|(_, tile)| corners.iter().all(|c| tile.corners[*c] == terrain))
+            .fold(0, |mask, (id, _)| mask | 1 << id)
+    }
+
+    //the tiles showing the terrain on at least one corner
+    fn containing(&self, terrain: Terrain) -> u64 {
+        self.tiles
+            .iter()
+            .enumerate()
+            .filter(|(_, tile)| tile.corners.contains(&terrain))
+            .fold(0, |mask, (id, _)| mask | 1 << id)
+    }
+
+    //the island is surrounded by sea, so the outer corners of the border cells must be sea.
+    fn border_mask(&self, grid: &Grid, x: usize, y: usize) -> u64 {
+        let mut mask = self.all_tiles();
+        if x == 0 {
+            mask &= self.with_corners(&[SW, NW], Terrain::Sea);
+        }
+        if x == grid.size_x - 1 {
+            mask &= self.with_corners(&[NE, SE], Terrain::Sea);
+        }
+        if y == 0 {
+            mask &= self.with_corners(&[SE, SW], Terrain::Sea);
+        }
+        if y == grid.size_y - 1 {
+            mask &= self.with_corners(&[NE, NW], Terrain::Sea);
+        }
+        mask
+    }
+
+    fn push(&self, grid: &mut Grid, hasher: SeaHasher, index: usize) {
+        let mut hasher = hasher;
+        hasher.write_usize(index);
+        let entropy = grid.domains[index].count_ones();
+        grid.heap.push(Reverse((entropy, hasher.finish(), index)));
+    }
+
+    //Removes the tiles that have no compatible neighbour, starting from the given cells.
+    //Returns the cell without any possibility left if there is a contradiction.
+    fn propagate(
+        &self,
+        grid: &mut Grid,
+        hasher: SeaHasher,
+        mut stack: Vec<usize>,
+    ) -> Result<(), usize> {
+        while let Some(index) = stack.pop() {
+            let domain = grid.domains[index];
+            for direction in 0..4 {
+                let neighbour = match grid.neighbour(index, direction) {
+                    Some(n) => n,
+                    None => continue,
+                };
+                let mut support = 0;
+                let mut remaining = domain;
+                while remaining != 0 {
+                    let id = remaining.trailing_zeros() as usize;
+                    support |= self.compatible[id][direction];
+                    remaining &= remaining - 1;
+                }
+                let new_domain = grid.domains[neighbour] & support;
+                if new_domain != grid.domains[neighbour] {
+                    if new_domain == 0 {
+                        return Err(neighbour);
+                    }
+                    grid.domains[neighbour] = new_domain;
+                    self.push(grid, hasher, neighbour);
+                    stack.push(neighbour);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    //Forgets everything in a square around a contradiction, the terrain preference included.
+    fn reset(&self, grid: &mut Grid, hasher: SeaHasher, index: usize, radius: usize) -> Vec<usize> {
+        let (x, y) = grid.coords(index);
+        let min_x = x.saturating_sub(radius + 1);
+        let min_y = y.saturating_sub(radius + 1);
+        let max_x = (x + radius + 1).min(grid.size_x - 1);
+        let max_y = (y + radius + 1).min(grid.size_y - 1);
+        let mut stack = Vec::new();
+        for i in min_x..=max_x {
+            for j in min_y..=max_y {
+                let cell = grid.index(i, j);
+                //the outer ring is kept, it constrains the reset cells.
+                let ring = i + radius + 1 == x
+                    || j + radius + 1 == y
+                    || i == x + radius + 1
+                    || j == y + radius + 1;
+                if !ring {
+                    grid.domains[cell] = self.border_mask(grid, i, j);
+                    grid.collapsed[cell] = false;
+                    self.push(grid, hasher, cell);
+                }
+                stack.push(cell);
+            }
+        }
+        stack
+    }
+
+    //Picks one of the possible tiles, favouring the ones looking the most like the generated terrain.
+    fn choose(&self, domain: u64, terrain: Terrain, mut hasher: SeaHasher) -> usize {
+        let weight = |id: usize| {
+            let same = self.tiles[id]
+                .corners
+                .iter()
+                .filter(|c| **c == terrain)
+                .count() as u64;
+            1 + 4 * same * same
+        };
+        let mut remaining = domain;
+        let mut total = 0;
+        while remaining != 0 {
+            total += weight(remaining.trailing_zeros() as usize);
+            remaining &= remaining - 1;
+        }
+        hasher.write_u64(0xC0_11A2_5E);
+        let mut pick = hasher.finish() % total;
+        let mut remaining = domain;
+        loop {
+            let id = remaining.trailing_zeros() as usize;
+            let w = weight(id);
+            if pick < w || remaining & (remaining - 1) == 0 {
+                return id;
+            }
+            pick -= w;
+            remaining &= remaining - 1;
+        }
+    }
+
+    //Solves the sprites of the island. kinds is indexed as [x][y].
+    //Returns the id of the wang tile for every cell, or None if the solver gave up.
+    pub fn solve(&self, kinds: &[Vec<TileKind>], hasher: SeaHasher) -> Option<Vec<Vec<usize>>> {
+        let size_x = kinds.len();
+        let size_y = kinds.first().map(|v| v.len()).unwrap_or(0);
+        if size_x == 0 || size_y == 0 {
+            return Some(Vec::new());
+        }
+        let mut grid = Grid {
+            size_x,
+            size_y,
+            domains: vec![0; size_x * size_y],
+            collapsed: vec![false; size_x * size_y],
+            heap: BinaryHeap::new(),
+        };
+        let mut stack = Vec::with_capacity(size_x * size_y);
+        let mut contradictions = Vec::new();
+        for x in 0..size_x {
+            for y in 0..size_y {
+                let index = grid.index(x, y);
+                let domain =
+                    self.border_mask(&grid, x, y) & self.containing(Terrain::of(kinds[x][y]));
+                grid.domains[index] = domain;
+                if domain == 0 {
+                    contradictions.push(index);
+                } else {
+                    self.push(&mut grid, hasher, index);
+                    stack.push(index);
+                }
+            }
+        }
+        let mut resets = 0;
+        let max_resets = MAX_RESETS_PER_CELL * size_x * size_y;
+        let mut radius = 0;
+        let mut pending = self.propagate(&mut grid, hasher, stack).err();
+        pending = pending.or_else(|| contradictions.pop());
+        loop {
+            //fix the contradictions first, with a larger area every time it fails
+            if let Some(index) = pending.take() {
+                resets += 1;
+                if resets > max_resets {
+                    return None;
+                }
+                let stack = self.reset(&mut grid, hasher, index, radius);
+                radius = (radius + 1).min(size_x.max(size_y));
+                pending = self
+                    .propagate(&mut grid, hasher, stack)
+                    .err()
+                    .or_else(|| contradictions.pop());
+                continue;
+            }
+            radius = 0;
+            let index = match grid.heap.pop() {
+                Some(Reverse((entropy, _, index))) => {
+                    if grid.collapsed[index] || entropy != grid.domains[index].count_ones() {
+                        continue;
+                    }
+                    //a reset can leave a cell without any tile, it is another contradiction
+                    if entropy == 0 {
+                        pending = Some(index);
+                        continue;
+                    }
+                    index
+                }
+                None => break,
+            };
+            let (x, y) = grid.coords(index);
+            let mut cell_hasher = hasher;
+            cell_hasher.write_usize(index);
+            cell_hasher.write_usize(resets);
+            let id = self.choose(grid.domains[index], Terrain::of(kinds[x][y]), cell_hasher);
+            grid.domains[index] = 1 << id;
+            grid.collapsed[index] = true;
+            pending = self.propagate(&mut grid, hasher, vec![index]).err();
+        }
+        //a cell left without any tile can't be drawn, the caller falls back to the rules
+        if grid.domains.iter().any(|domain| *domain == 0) {
+            return None;
+        }
+        Some(
+            (0..size_x)
+                .map(|x| {
+                    (0..size_y)
+                        .map(|y| grid.domains[grid.index(x, y)].trailing_zeros() as usize)
+                        .collect()
+                })
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use seahash::SeaHasher;
+
+    use super::*;
+    use crate::sea::player::CollisionType;
+
+    //every combination of sea and sand on the corners
+    fn sea_and_sand() -> Vec<WangTile> {
+        (0..16)
+            .map(|bits| {
+                let terrain = |corner: usize| {
+                    if bits & (1 << corner) == 0 {
+                        Terrain::Sea
+                    } else {
+                        Terrain::Sand
+                    }
+                };
+                WangTile {
+                    corners: [terrain(NE), terrain(SE), terrain(SW), terrain(NW)],
+                    sprite: Some(bits),
+                    variants: 1,
+                    collision: CollisionType::None,
+                }
+            })
+            .collect()
+    }
+
+    fn empty_grid(solver: &ConstraintSolver, size_x: usize, size_y: usize) -> Grid {
+        Grid {
+            size_x,
+            size_y,
+            domains: vec![solver.all_tiles(); size_x * size_y],
+            collapsed: vec![false; size_x * size_y],
+            heap: BinaryHeap::new(),
+        }
+    }
+
+    #[test]
+    fn compatible_masks_match_the_shared_corners() {
+        let tiles = sea_and_sand();
+        let solver = ConstraintSolver::new(&tiles).unwrap();
+        for (id, tile) in tiles.iter().enumerate() {
+            for (other_id, other) in tiles.iter().enumerate() {
+                let c = &tile.corners;
+                let o = &other.corners;
+                let expected = [
+                    o[SE] == c[NE] && o[SW] == c[NW],
+                    o[NW] == c[NE] && o[SW] == c[SE],
+                    o[NE] == c[SE] && o[NW] == c[SW],
+                    o[NE] == c[NW] && o[SE] == c[SW],
+                ];
+                for direction in 0..4 {
+                    let fits = solver.compatible[id][direction] & (1 << other_id) != 0;
+                    assert_eq!(fits, expected[direction]);
+                }
+            }
+        }
+        //with two terrains, a tile has 4 possible neighbours on each side
+        for masks in solver.compatible.iter() {
+            assert!(masks.iter().all(|mask| mask.count_ones() == 4));
+        }
+    }
+
+    #[test]
+    fn propagate_restricts_the_neighbours() {
+        let tiles = sea_and_sand();
+        let solver = ConstraintSolver::new(&tiles).unwrap();
+        let mut grid = empty_grid(&solver, 3, 3);
+        let center = grid.index(1, 1);
+        //full sand in the middle
+        grid.domains[center] = 1 << 15;
+        solver
+            .propagate(&mut grid, SeaHasher::new(), vec![center])
+            .unwrap();
+        let east = grid.index(2, 1);
+        assert_eq!(grid.domains[east], solver.compatible[15][1]);
+        for (id, tile) in tiles.iter().enumerate() {
+            if grid.domains[east] & (1 << id) != 0 {
+                assert_eq!(tile.corners[NW], Terrain::Sand);
+                assert_eq!(tile.corners[SW], Terrain::Sand);
+            }
+        }
+        //the corners only touch the middle by a corner, they still keep the tiles with sand there
+        let north_east = grid.index(2, 2);
+        assert_eq!(
+            grid.domains[north_east],
+            solver.with_corners(&[SW], Terrain::Sand)
+        );
+    }
+
+    #[test]
+    fn propagate_reports_contradictions() {
+        let tiles = sea_and_sand();
+        let solver = ConstraintSolver::new(&tiles).unwrap();
+        let mut grid = empty_grid(&solver, 2, 1);
+        let (west, east) = (grid.index(0, 0), grid.index(1, 0));
+        //full sand can't be next to full sea
+        grid.domains[west] = 1 << 15;
+        grid.domains[east] = 1;
+        assert_eq!(
+            solver.propagate(&mut grid, SeaHasher::new(), vec![west]),
+            Err(east)
+        );
+    }
+
+    #[test]
+    fn solved_tiles_fit_together() {
+        let tiles = sea_and_sand();
+        let solver = ConstraintSolver::new(&tiles).unwrap();
+        let sand = TileKind::Sand(false);
+        let sea = TileKind::Sea(false);
+        let kinds = vec![
+            vec![sea, sea, sea, sea],
+            vec![sea, sand, sand, sea],
+            vec![sea, sand, sand, sea],
+            vec![sea, sea, sea, sea],
+        ];
+        let ids = solver.solve(&kinds, SeaHasher::new()).unwrap();
+        for x in 0..4 {
+            for y in 0..4 {
+                let tile = solver.tile(ids[x][y]);
+                if x + 1 < 4 {
+                    let east = solver.tile(ids[x + 1][y]);
+                    assert_eq!(tile.corners[NE], east.corners[NW]);
+                    assert_eq!(tile.corners[SE], east.corners[SW]);
+                }
+                if y + 1 < 4 {
+                    let north = solver.tile(ids[x][y + 1]);
+                    assert_eq!(tile.corners[NE], north.corners[SE]);
+                    assert_eq!(tile.corners[NW], north.corners[SW]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn unsolvable_tileset_gives_up() {
+        //without any sea, the border of the island can't be drawn
+        let tiles = vec![WangTile {
+            corners: [Terrain::Sand; 4],
+            sprite: Some(0),
+            variants: 1,
+            collision: CollisionType::None,
+        }];
+        let solver = ConstraintSolver::new(&tiles).unwrap();
+        let kinds = vec![vec![TileKind::Sand(false); 2]; 2];
+        assert_eq!(solver.solve(&kinds, SeaHasher::new()), None);
+    }
+}
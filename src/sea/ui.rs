@@ -0,0 +1,141 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+use egui::Visuals;
+
+use crate::{clock::WorldClock, loading::GameState};
+
+use super::{
+    combat::{Cannons, Side},
+    player::{Player, ShipSunkEvent},
+    sailing::{rounded_heading, wind_angle, ShipMotion},
+    ships::Hull,
+    weather::{CurrentWeather, Weather},
+    wind::{optimal_trim, Wind},
+    SeaPaused,
+};
+
+pub struct SeaUiPlugin;
+
+impl Plugin for SeaUiPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.on_state_update(GameState::STAGE, GameState::Sea, wind_ui_system.system())
+            .on_state_update(
+                GameState::STAGE,
+                GameState::Sea,
+                shipwreck_ui_system.system(),
+            );
+    }
+}
+
+//size of the wind indicator, in points
+const INDICATOR_SIZE: f32 = 64.;
+//how long the shipwreck notice stays, in seconds
+const SHIPWRECK_NOTICE_TIME: f32 = 5.;
+
+fn wind_ui_system(
+    mut egui_context: ResMut<EguiContext>,
+    wind: Res<Wind>,
+    weather: Res<Weather>,
+    current_weather: Res<CurrentWeather>,
+    clock: Res<WorldClock>,
+    player_query: Query<(&ShipMotion, &Hull, &Cannons, &Transform), With<Player>>,
+    paused: Res<SeaPaused>,
+) {
+    if paused.0 {
+        return;
+    }
+    let ctx = &mut egui_context.ctx;
+    for (player, hull, cannons, transform) in player_query.iter() {
+        let wind_here = weather.wind(&wind, transform.translation.truncate());
+        let heading = rounded_heading(player.rotation);
+        let angle = wind_angle(player.rotation, wind_here);
+        egui::Area::new("wind")
+            .fixed_pos(egui::pos2(4.0, 4.0))
+            .show(ctx, |ui| {
+                *ui.visuals_mut() = Visuals::light();
+
+                egui::Frame::group(ui.style())
+                    .fill(egui::Color32::WHITE)
+                    .show(ui, |ui| {
+                        let (rect, _) = ui.allocate_exact_size(
+                            egui::vec2(INDICATOR_SIZE, INDICATOR_SIZE),
+                            egui::Sense::hover(),
+                        );
+                        let center = rect.center();
+                        let radius = INDICATOR_SIZE / 2. - 2.;
+                        let painter = ui.painter();
+                        painter.circle_stroke(center, radius, (1., egui::Color32::GRAY));
+                        //the screen y axis goes down
+                        let to_screen = |v: Vec2| center + egui::vec2(v.x, -v.y) * radius;
+                        painter.line_segment(
+                            [center, to_screen(heading * 0.8)],
+                            (2., egui::Color32::from_rgb(139, 69, 19)),
+                        );
+                        //the wind arrow goes through the indicator, longer when the wind is stronger
+                        let wind_direction = wind_here.normalize();
+                        let tail = to_screen(-wind_direction * wind_here.length());
+                        let tip = to_screen(wind_direction * wind_here.length());
+                        let stroke = (2., egui::Color32::from_rgb(30, 144, 255));
+                        painter.line_segment([tail, tip], stroke);
+                        let side = Vec2::new(-wind_direction.y, wind_direction.x) * 0.2;
+                        let back = wind_direction * wind_here.length() - wind_direction * 0.3;
+                        painter.line_segment([tip, to_screen(back + side)], stroke);
+                        painter.line_segment([tip, to_screen(back - side)], stroke);
+                        let minutes = (clock.hour() * 60.) as u32;
+                        ui.label(format!(
+                            "Day {} {:02}:{:02}",
+                            clock.day + 1,
+                            minutes / 60,
+                            minutes % 60
+                        ));
+                        ui.label(format!("{:?}", current_weather.kind));
+                        ui.label(format!("Hull {:.0}/{:.0}", hull.points.ceil(), hull.max));
+                        ui.label(format!("Wind {:.0}%", wind_here.length() * 100.));
+                        ui.label(format!("Sails {:.0}%", player.sail * 100.));
+                        ui.label(format!(
+                            "Trim {:.0}% (best {:.0}%)",
+                            player.trim * 100.,
+                            optimal_trim(angle) * 100.
+                        ));
+                        let loaded = |broadside| {
+                            if cannons.is_loaded(broadside) {
+                                "ready"
+                            } else {
+                                "reloading"
+                            }
+                        };
+                        ui.label(format!("Port (Q) {}", loaded(Side::Port)));
+                        ui.label(format!("Starboard (E) {}", loaded(Side::Starboard)));
+                    });
+            });
+    }
+}
+
+fn shipwreck_ui_system(
+    mut egui_context: ResMut<EguiContext>,
+    time: Res<Time>,
+    mut sunk_reader: EventReader<ShipSunkEvent>,
+    mut notice_left: Local<f32>,
+) {
+    if sunk_reader.iter().next().is_some() {
+        *notice_left = SHIPWRECK_NOTICE_TIME;
+    }
+    if *notice_left <= 0. {
+        return;
+    }
+    *notice_left -= time.delta_seconds();
+    let ctx = &mut egui_context.ctx;
+    egui::Area::new("shipwreck")
+        .fixed_pos(egui::pos2(200.0, 100.0))
+        .show(ctx, |ui| {
+            *ui.visuals_mut() = Visuals::light();
+
+            egui::Frame::group(ui.style())
+                .fill(egui::Color32::WHITE)
+                .show(ui, |ui| {
+                    ui.label(
+                        "Your ship sank. You wash up where your journey began, with a new ship.",
+                    );
+                });
+        });
+}
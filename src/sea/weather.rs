@@ -0,0 +1,326 @@
+use std::hash::{Hash, Hasher};
+
+use bevy::{prelude::*, render::camera::Camera};
+use noise::{NoiseFn, Perlin, Seedable};
+use seahash::SeaHasher;
+use serde::{Deserialize, Serialize};
+
+use crate::{loading::GameState, util::SeededHasher};
+
+use super::{player::Player, wind::Wind, SeaPaused};
+
+//how many spells back the weather chain starts, it goes further back until it doesn't matter where it started
+const WEATHER_MEMORY: i64 = 4;
+const MAX_WEATHER_MEMORY: i64 = 256;
+//size of the gust patterns, in world units
+const GUST_SCALE: f64 = 1. / 500.;
+//how fast the gusts change, in seconds
+const GUST_TIME_SCALE: f64 = 1. / 4.;
+//how fast the overlay follows the weather, per second
+const OVERLAY_SPEED: f32 = 0.5;
+const OVERLAY_SIZE: f32 = 10000.;
+
+pub struct SeaWeatherPlugin;
+impl Plugin for SeaWeatherPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Weather>()
+            .init_resource::<CurrentWeather>()
+            .add_startup_system(setup.system())
+            .on_state_enter(GameState::STAGE, GameState::Sea, spawn_overlay.system())
+            .on_state_exit(GameState::STAGE, GameState::Sea, despawn_overlay.system())
+            .on_state_update(GameState::STAGE, GameState::Sea, weather_system.system())
+            .on_state_update(GameState::STAGE, GameState::Sea, overlay_system.system());
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum WeatherKind {
+    Clear,
+    Overcast,
+    Rain,
+    Storm,
+    Fog,
+}
+
+//What a kind of weather does to the sea.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WeatherState {
+    pub kind: WeatherKind,
+    //multiplies the strength of the wind
+    pub wind: f32,
+    //seconds between two frames of the sea animation
+    pub wave_period: f32,
+    //how hard the gusts push the ships, in world units per second
+    pub gusts: f32,
+    //part of the sight left, for charting the sea and for the pirates looking for prey
+    pub sight: f32,
+    //color drawn over the screen, it is what hides the islands in fog, they are still loaded around the screen
+    pub overlay: (f32, f32, f32, f32),
+    //the weather of the next spell, with its weight
+    pub next: Vec<(WeatherKind, u32)>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WeatherConfig {
+    pub cell_size: f32,
+    pub spell_length: f32,
+    pub drift: (f32, f32),
+    pub states: Vec<WeatherState>,
+}
+
+fn read_weather_config() -> WeatherConfig {
+    let weather_config_string =
+        std::fs::read_to_string("config/weather.ron").expect("weather config file not found");
+    ron::from_str(&weather_config_string).expect("syntax error on weather config file")
+}
+
+//The weather over the sea. The world is cut in cells drifting with time,
+//each cell goes from a weather to the next, a spell after the other, following the config.
+#[derive(Default)]
+pub struct Weather {
+    config: Option<WeatherConfig>,
+    hasher: SeaHasher,
+    gust_noise: Perlin,
+    time: f64,
+}
+impl Weather {
+    pub fn new(mut hasher: SeaHasher, config: WeatherConfig) -> Self {
+        hasher.write(b"weather");
+        Weather {
+            gust_noise: Perlin::new().set_seed(hasher.finish() as u32),
+            config: Some(config),
+            hasher,
+            time: 0.,
+        }
+    }
+
+    //The kind of weather at a world position.
+    //The spells of a cell follow each other as the config says: the chain is run from every kind of weather,
+    //from further back until they all end the same, so the weather doesn't depend on where the chain started.
+    pub fn sample(&self, position: Vec2) -> WeatherKind {
+        let config = match &self.config {
+            Some(config) => config,
+            None => return WeatherKind::Clear,
+        };
+        let drift = Vec2::new(config.drift.0, config.drift.1) * self.time as f32;
+        let cell = ((position - drift) / config.cell_size).floor();
+        let cell = (cell.x as i32, cell.y as i32);
+        //the cells don't all change at the same time
+        let mut cell_hasher = self.hasher;
+        cell.hash(&mut cell_hasher);
+        let offset = (cell_hasher.finish() % 1000) as f64 / 1000.;
+        let spell = (self.time / config.spell_length as f64 + offset).floor() as i64;
+
+        let mut memory = WEATHER_MEMORY;
+        loop {
+            let hashes: Vec<u64> = (spell - memory..=spell)
+                .map(|spell| {
+                    let mut spell_hasher = cell_hasher;
+                    spell_hasher.write_i64(spell);
+                    spell_hasher.finish()
+                })
+                .collect();
+            let mut ends = config.states.iter().map(|state| {
+                hashes
+                    .iter()
+                    .fold(state.kind, |kind, hash| config.next_kind(kind, *hash))
+            });
+            let first = match ends.next() {
+                Some(kind) => kind,
+                None => return WeatherKind::Clear,
+            };
+            if ends.all(|kind| kind == first) || memory >= MAX_WEATHER_MEMORY {
+                return first;
+            }
+            memory *= 2;
+        }
+    }
+
+    pub fn state(&self, position: Vec2) -> Option<&WeatherState> {
+        let kind = self.sample(position);
+        self.config.as_ref().map(|config| config.state(kind))
+    }
+
+    //The wind, blowing harder in bad weather.
+    pub fn wind(&self, wind: &Wind, position: Vec2) -> Vec2 {
+        let factor = self.state(position).map(|state| state.wind).unwrap_or(1.);
+        wind.sample(position) * factor
+    }
+
+    //How the gusts push a ship around, in world units per second.
+    pub fn gust(&self, position: Vec2) -> Vec2 {
+        let strength = self.state(position).map(|state| state.gusts).unwrap_or(0.);
+        if strength <= 0. {
+            return Vec2::zero();
+        }
+        let x = position.x as f64 * GUST_SCALE;
+        let y = position.y as f64 * GUST_SCALE;
+        let t = self.time * GUST_TIME_SCALE;
+        Vec2::new(
+            self.gust_noise.get([x, y, t]) as f32,
+            self.gust_noise.get([x + 100., y, t]) as f32,
+        ) * strength
+    }
+
+    pub fn sight(&self, position: Vec2) -> f32 {
+        self.state(position).map(|state| state.sight).unwrap_or(1.)
+    }
+}
+
+impl WeatherConfig {
+    fn state(&self, kind: WeatherKind) -> &WeatherState {
+        self.states
+            .iter()
+            .find(|state| state.kind == kind)
+            .expect("weather missing from the weather config")
+    }
+
+    //draws the weather following another one
+    fn next_kind(&self, kind: WeatherKind, hash: u64) -> WeatherKind {
+        let next = &self.state(kind).next;
+        let total: u32 = next.iter().map(|(_, weight)| weight).sum();
+        if total == 0 {
+            return kind;
+        }
+        let mut value = (hash % total as u64) as u32;
+        for (next_kind, weight) in next.iter() {
+            if value < *weight {
+                return *next_kind;
+            }
+            value -= weight;
+        }
+        kind
+    }
+}
+
+//The weather where the player is.
+#[derive(Debug)]
+pub struct CurrentWeather {
+    pub kind: WeatherKind,
+    pub wave_period: f32,
+    pub sight: f32,
+    pub overlay: Color,
+}
+impl Default for CurrentWeather {
+    fn default() -> Self {
+        CurrentWeather {
+            kind: WeatherKind::Clear,
+            wave_period: 0.5,
+            sight: 1.,
+            overlay: Color::rgba(1., 1., 1., 0.),
+        }
+    }
+}
+
+struct WeatherOverlay;
+
+fn setup(hasher: Res<SeededHasher>, mut weather: ResMut<Weather>) {
+    *weather = Weather::new(hasher.get_hasher(), read_weather_config());
+}
+
+fn weather_system(
+    time: Res<Time>,
+    mut weather: ResMut<Weather>,
+    mut current: ResMut<CurrentWeather>,
+    player_query: Query<&Transform, With<Player>>,
+    paused: Res<SeaPaused>,
+) {
+    if paused.0 {
+        return;
+    }
+    weather.time += time.delta_seconds_f64();
+    for transform in player_query.iter() {
+        if let Some(state) = weather.state(transform.translation.truncate()) {
+            let (r, g, b, a) = state.overlay;
+            *current = CurrentWeather {
+                kind: state.kind,
+                wave_period: state.wave_period,
+                sight: state.sight,
+                overlay: Color::rgba(r, g, b, a),
+            };
+        }
+    }
+}
+
+fn spawn_overlay(commands: &mut Commands, mut materials: ResMut<Assets<ColorMaterial>>) {
+    commands
+        .spawn(SpriteBundle {
+            material: materials.add(Color::rgba(1., 1., 1., 0.).into()),
+            sprite: Sprite::new(Vec2::new(OVERLAY_SIZE, OVERLAY_SIZE)),
+            transform: Transform::from_translation(Vec3::new(0., 0., 500.)),
+            ..Default::default()
+        })
+        .with(WeatherOverlay);
+}
+
+fn despawn_overlay(commands: &mut Commands, overlay_query: Query<Entity, With<WeatherOverlay>>) {
+    for entity in overlay_query.iter() {
+        commands.despawn_recursive(entity);
+    }
+}
+
+//The overlay follows the camera, and fades from a weather to the next.
+fn overlay_system(
+    time: Res<Time>,
+    current: Res<CurrentWeather>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    camera_query: Query<(&Camera, &Transform)>,
+    mut overlay_query: Query<(&Handle<ColorMaterial>, &mut Transform), With<WeatherOverlay>>,
+) {
+    let step = (OVERLAY_SPEED * time.delta_seconds()).min(1.);
+    for (handle, mut transform) in overlay_query.iter_mut() {
+        for (_, camera_transform) in camera_query.iter() {
+            transform.translation.x = camera_transform.translation.x;
+            transform.translation.y = camera_transform.translation.y;
+        }
+        if let Some(material) = materials.get_mut(handle) {
+            let from = material.color;
+            let to = current.overlay;
+            let mix = |from: f32, to: f32| from + (to - from) * step;
+            material.color = Color::rgba(
+                mix(from.r(), to.r()),
+                mix(from.g(), to.g()),
+                mix(from.b(), to.b()),
+                mix(from.a(), to.a()),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spells_follow_the_config() {
+        let weather_config = read_weather_config();
+        let spell_length = weather_config.spell_length as f64;
+        let cell_size = weather_config.cell_size;
+        let drift = Vec2::new(weather_config.drift.0, weather_config.drift.1);
+        let mut weather = Weather::new(SeaHasher::new(), weather_config);
+        for cell in 0..4 {
+            //in the middle of a cell
+            let position = Vec2::new(cell as f32 * 10. + 0.5, 0.5) * cell_size;
+            let mut previous = None;
+            for spell in 0..200 {
+                weather.time = (spell as f64 + 0.5) * spell_length;
+                //the cell drifts with the weather
+                let kind = weather.sample(position + drift * weather.time as f32);
+                if let Some(previous) = previous {
+                    let config = weather.config.as_ref().unwrap();
+                    assert!(
+                        config
+                            .state(previous)
+                            .next
+                            .iter()
+                            .any(|(next, weight)| *next == kind && *weight > 0),
+                        "{:?} followed {:?}",
+                        kind,
+                        previous
+                    );
+                }
+                previous = Some(kind);
+            }
+        }
+    }
+}
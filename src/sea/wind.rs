@@ -0,0 +1,96 @@
+use std::{f32::consts::PI, hash::Hasher};
+
+use bevy::prelude::*;
+use noise::{NoiseFn, Perlin, Seedable};
+
+use crate::{loading::GameState, util::SeededHasher};
+
+use super::SeaPaused;
+
+//size of the wind patterns, in world units
+const WIND_SCALE: f64 = 1. / 4000.;
+//how fast the wind changes, in seconds
+const WIND_TIME_SCALE: f64 = 1. / 120.;
+//the wind never completely stops
+const MIN_STRENGTH: f32 = 0.2;
+
+pub struct SeaWindPlugin;
+impl Plugin for SeaWindPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Wind>()
+            .add_startup_system(setup.system())
+            .on_state_update(GameState::STAGE, GameState::Sea, wind_system.system());
+    }
+}
+
+//The wind over the sea, it varies slowly with the position and the time.
+#[derive(Default)]
+pub struct Wind {
+    direction_noise: Perlin,
+    strength_noise: Perlin,
+    time: f64,
+}
+impl Wind {
+    pub fn new(mut hasher: impl Hasher) -> Self {
+        hasher.write(b"wind");
+        let direction_seed = hasher.finish() as u32;
+        hasher.write(b"strength");
+        Wind {
+            direction_noise: Perlin::new().set_seed(direction_seed),
+            strength_noise: Perlin::new().set_seed(hasher.finish() as u32),
+            time: 0.,
+        }
+    }
+
+    //The direction the wind blows to, its length is the strength of the wind, between MIN_STRENGTH and 1.
+    pub fn sample(&self, position: Vec2) -> Vec2 {
+        let point = [
+            position.x as f64 * WIND_SCALE,
+            position.y as f64 * WIND_SCALE,
+            self.time * WIND_TIME_SCALE,
+        ];
+        let angle = self.direction_noise.get(point) as f32 * 2. * PI;
+        let strength = (self.strength_noise.get(point) as f32 * 0.5 + 0.5)
+            .max(0.)
+            .min(1.);
+        let (s, c) = angle.sin_cos();
+        Vec2::new(c, s) * (MIN_STRENGTH + (1. - MIN_STRENGTH) * strength)
+    }
+}
+
+//How well a ship sails with the wind coming from that angle.
+//0 means the wind blows from behind the ship, PI that the ship faces it.
+pub fn point_of_sail_efficiency(angle: f32) -> f32 {
+    //running, broad reach, beam reach, close hauled and in irons
+    const POLAR: [(f32, f32); 5] = [
+        (0., 0.6),
+        (PI / 4., 0.85),
+        (PI / 2., 1.),
+        (3. * PI / 4., 0.7),
+        (PI, 0.05),
+    ];
+    let angle = angle.abs().min(PI);
+    for window in POLAR.windows(2) {
+        let ((a0, e0), (a1, e1)) = (window[0], window[1]);
+        if angle <= a1 {
+            return e0 + (e1 - e0) * (angle - a0) / (a1 - a0);
+        }
+    }
+    POLAR[POLAR.len() - 1].1
+}
+
+//The best trim for a point of sail: eased out (0) when running, sheeted in (1) when close hauled.
+pub fn optimal_trim(angle: f32) -> f32 {
+    angle.abs().min(PI) / PI
+}
+
+fn setup(hasher: Res<SeededHasher>, mut wind: ResMut<Wind>) {
+    *wind = Wind::new(hasher.get_hasher());
+}
+
+fn wind_system(time: Res<Time>, mut wind: ResMut<Wind>, paused: Res<SeaPaused>) {
+    if paused.0 {
+        return;
+    }
+    wind.time += time.delta_seconds_f64();
+}
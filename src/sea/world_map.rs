@@ -0,0 +1,291 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+
+use crate::loading::GameState;
+
+use super::{
+    exploration::Exploration, map::TileKind, markers::Markers, player::Player, sailing::ShipMotion,
+    SeaPaused, ISLAND_SCALING, TILE_SIZE,
+};
+
+//size of a tile on the map, in points
+const MIN_ZOOM: f32 = 1.;
+const MAX_ZOOM: f32 = 16.;
+const DEFAULT_ZOOM: f32 = 4.;
+//the tiles are drawn by groups when they are smaller than that, in points
+const MIN_CELL_SIZE: f32 = 6.;
+//how fast the keys move the map, in points per second
+const PAN_SPEED: f32 = 400.;
+const ZOOM_SPEED: f32 = 2.;
+
+const FOG_COLOR: egui::Color32 = egui::Color32::from_rgb(40, 40, 48);
+const SEA_COLOR: egui::Color32 = egui::Color32::from_rgb(70, 130, 180);
+const SAND_COLOR: egui::Color32 = egui::Color32::from_rgb(230, 210, 150);
+const FOREST_COLOR: egui::Color32 = egui::Color32::from_rgb(60, 130, 60);
+const PLAYER_COLOR: egui::Color32 = egui::Color32::from_rgb(200, 30, 30);
+const MARKER_COLOR: egui::Color32 = egui::Color32::from_rgb(250, 200, 40);
+const MARKERS_PANEL_WIDTH: f32 = 200.;
+
+pub struct SeaWorldMapPlugin;
+impl Plugin for SeaWorldMapPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<WorldMapView>()
+            .on_state_update(GameState::STAGE, GameState::Sea, open_map_system.system())
+            .on_state_update(GameState::STAGE, GameState::Sea, map_input_system.system())
+            .on_state_update(GameState::STAGE, GameState::Sea, map_ui_system.system())
+            .on_state_exit(GameState::STAGE, GameState::Sea, close_map_system.system());
+    }
+}
+
+//What the map shows: its center, in tiles, and the size of a tile, in points.
+pub struct WorldMapView {
+    pub center: Vec2,
+    pub zoom: f32,
+}
+impl Default for WorldMapView {
+    fn default() -> Self {
+        WorldMapView {
+            center: Vec2::zero(),
+            zoom: DEFAULT_ZOOM,
+        }
+    }
+}
+
+//M opens the map over the sea, centered on the player, and pauses the sea. M or Escape close it.
+//The keys are left to the text fields while one is being typed in.
+fn open_map_system(
+    egui_context: Res<EguiContext>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut paused: ResMut<SeaPaused>,
+    mut view: ResMut<WorldMapView>,
+    player_query: Query<&Transform, With<Player>>,
+) {
+    if egui_context.ctx.wants_keyboard_input() {
+        return;
+    }
+    if paused.0 {
+        if keyboard_input.just_pressed(KeyCode::M) || keyboard_input.just_pressed(KeyCode::Escape) {
+            paused.0 = false;
+        }
+    } else if keyboard_input.just_pressed(KeyCode::M) {
+        paused.0 = true;
+        for transform in player_query.iter() {
+            view.center = transform.translation.truncate() / (TILE_SIZE as f32 * ISLAND_SCALING);
+        }
+    }
+}
+
+fn close_map_system(mut paused: ResMut<SeaPaused>) {
+    paused.0 = false;
+}
+
+//The arrows move the map, + and - zoom.
+fn map_input_system(
+    time: Res<Time>,
+    egui_context: Res<EguiContext>,
+    keyboard_input: Res<Input<KeyCode>>,
+    paused: Res<SeaPaused>,
+    mut view: ResMut<WorldMapView>,
+) {
+    if !paused.0 || egui_context.ctx.wants_keyboard_input() {
+        return;
+    }
+    let mut direction = Vec2::zero();
+    if keyboard_input.pressed(KeyCode::Left) {
+        direction.x -= 1.;
+    }
+    if keyboard_input.pressed(KeyCode::Right) {
+        direction.x += 1.;
+    }
+    if keyboard_input.pressed(KeyCode::Up) {
+        direction.y += 1.;
+    }
+    if keyboard_input.pressed(KeyCode::Down) {
+        direction.y -= 1.;
+    }
+    let zoom = view.zoom;
+    view.center += direction * PAN_SPEED / zoom * time.delta_seconds();
+    let zoom_factor = ZOOM_SPEED.powf(time.delta_seconds());
+    if keyboard_input.pressed(KeyCode::Equals) {
+        view.zoom = (zoom * zoom_factor).min(MAX_ZOOM);
+    } else if keyboard_input.pressed(KeyCode::Minus) {
+        view.zoom = (zoom / zoom_factor).max(MIN_ZOOM);
+    }
+}
+
+//Draws the explored sea and the charted islands, the mouse drags the map and its wheel zooms.
+fn map_ui_system(
+    mut egui_context: ResMut<EguiContext>,
+    exploration: Res<Exploration>,
+    paused: Res<SeaPaused>,
+    mut markers: ResMut<Markers>,
+    mut view: ResMut<WorldMapView>,
+    mut new_marker_name: Local<String>,
+    player_query: Query<(&Transform, &ShipMotion), With<Player>>,
+) {
+    if !paused.0 {
+        return;
+    }
+    let ctx = &mut egui_context.ctx;
+    //the markers are dropped at the center of the map
+    egui::SidePanel::left("markers", MARKERS_PANEL_WIDTH).show(ctx, |ui| {
+        ui.heading("Markers");
+        ui.text_edit_singleline(&mut *new_marker_name);
+        if ui.button("Drop marker at the center").clicked() {
+            let name = if new_marker_name.is_empty() {
+                format!("Marker {}", markers.markers.len() + 1)
+            } else {
+                std::mem::take(&mut *new_marker_name)
+            };
+            markers.add(name, view.center, &exploration);
+        }
+        ui.separator();
+        let mut removed = None;
+        let mut waypoint = markers.waypoint;
+        for marker in markers.markers.iter() {
+            let active = markers.waypoint == Some(marker.id);
+            ui.label(if active {
+                format!("> {}", marker.name)
+            } else {
+                marker.name.clone()
+            });
+            ui.horizontal(|ui| {
+                if ui.button("Show").clicked() {
+                    view.center = Vec2::new(marker.position.0, marker.position.1);
+                }
+                if active {
+                    if ui.button("Clear waypoint").clicked() {
+                        waypoint = None;
+                    }
+                } else if ui.button("Set waypoint").clicked() {
+                    waypoint = Some(marker.id);
+                }
+                if ui.button("Delete").clicked() {
+                    removed = Some(marker.id);
+                }
+            });
+        }
+        markers.waypoint = waypoint;
+        if let Some(id) = removed {
+            markers.remove(id);
+        }
+    });
+    egui::CentralPanel::default().show(ctx, |ui| {
+        ui.label("M or Escape: close the map, arrows or drag: move, + - or wheel: zoom");
+        let (rect, response) = ui.allocate_exact_size(ui.available_size(), egui::Sense::drag());
+        //the screen y axis goes down
+        let drag = response.drag_delta();
+        let zoom = view.zoom;
+        view.center += Vec2::new(-drag.x, drag.y) / zoom;
+        if response.hovered() {
+            let scroll = ui.input().scroll_delta.y;
+            view.zoom = (zoom * (1. + scroll * 0.002)).max(MIN_ZOOM).min(MAX_ZOOM);
+        }
+        let zoom = view.zoom;
+        let center = view.center;
+        let to_screen =
+            |tile: Vec2| rect.center() + egui::vec2(tile.x - center.x, center.y - tile.y) * zoom;
+        let cell = (MIN_CELL_SIZE / zoom).ceil().max(1.) as i32;
+        let half = Vec2::new(rect.width(), rect.height()) / 2. / zoom;
+        let min = (
+            ((center.x - half.x).floor() as i32).div_euclid(cell) * cell,
+            ((center.y - half.y).floor() as i32).div_euclid(cell) * cell,
+        );
+        let max = (
+            (center.x + half.x).ceil() as i32 + cell,
+            (center.y + half.y).ceil() as i32 + cell,
+        );
+        let cell_rect = |x: i32, y: i32, width: i32| {
+            egui::Rect::from_min_max(
+                to_screen(Vec2::new(x as f32, (y + cell) as f32)),
+                to_screen(Vec2::new((x + width) as f32, y as f32)),
+            )
+        };
+        let painter = ui.painter();
+        painter.rect_filled(rect, 0., FOG_COLOR);
+
+        //the revealed sea, the cells of a row are merged
+        for y in (min.1..max.1).step_by(cell as usize) {
+            let mut run_start = None;
+            for x in (min.0..=max.0).step_by(cell as usize) {
+                let revealed = x < max.0 && exploration.is_revealed((x, y));
+                match (revealed, run_start) {
+                    (true, None) => run_start = Some(x),
+                    (false, Some(start)) => {
+                        painter.rect_filled(cell_rect(start, y, x - start), 0., SEA_COLOR);
+                        run_start = None;
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        for island in exploration.islands.values() {
+            let width = island.tiles.len() as i32;
+            let height = island.tiles.first().map(|column| column.len()).unwrap_or(0) as i32;
+            if island.min_x + width < min.0
+                || island.min_x > max.0
+                || island.min_y + height < min.1
+                || island.min_y > max.1
+            {
+                continue;
+            }
+            let first = |start: i32| start.div_euclid(cell) * cell;
+            for x in (first(island.min_x)..island.min_x + width).step_by(cell as usize) {
+                for y in (first(island.min_y)..island.min_y + height).step_by(cell as usize) {
+                    let kind = island
+                        .tiles
+                        .get((x - island.min_x).max(0) as usize)
+                        .and_then(|column| column.get((y - island.min_y).max(0) as usize));
+                    let color = match kind {
+                        Some(TileKind::Sand(_)) => SAND_COLOR,
+                        Some(TileKind::Forest) => FOREST_COLOR,
+                        _ => continue,
+                    };
+                    if exploration.is_revealed((x, y)) {
+                        painter.rect_filled(cell_rect(x, y, cell), 0., color);
+                    }
+                }
+            }
+        }
+
+        for marker in markers.markers.iter() {
+            let position = to_screen(Vec2::new(marker.position.0, marker.position.1));
+            let radius = if markers.waypoint == Some(marker.id) {
+                6.
+            } else {
+                4.
+            };
+            painter.circle_filled(position, radius, MARKER_COLOR);
+            painter.circle_stroke(position, radius, (1., egui::Color32::BLACK));
+        }
+        //the crosshair shows where the next marker goes
+        let crosshair = rect.center();
+        let stroke = (1., egui::Color32::WHITE);
+        painter.line_segment(
+            [
+                crosshair - egui::vec2(6., 0.),
+                crosshair + egui::vec2(6., 0.),
+            ],
+            stroke,
+        );
+        painter.line_segment(
+            [
+                crosshair - egui::vec2(0., 6.),
+                crosshair + egui::vec2(0., 6.),
+            ],
+            stroke,
+        );
+
+        for (transform, motion) in player_query.iter() {
+            let player = transform.translation.truncate() / (TILE_SIZE as f32 * ISLAND_SCALING);
+            let position = to_screen(player);
+            let heading = motion.heading();
+            painter.circle_filled(position, 4., PLAYER_COLOR);
+            painter.line_segment(
+                [position, position + egui::vec2(heading.x, -heading.y) * 12.],
+                (2., PLAYER_COLOR),
+            );
+        }
+    });
+}
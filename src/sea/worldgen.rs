@@ -1,27 +1,31 @@
 use crate::{land::mobs::Mob, loading::GameState, util::SeededHasher};
 
+use super::map::TileKind;
 use super::{
+    autotile::{sprite_id, Terrain, TileSet, TILE_VARIANTS},
     loader::BiomeConfig,
-    map::TileKind::*,
+    map::{Islands, TileKind::*},
     player::{CollisionType, PlayerPositionUpdate},
+    regions::BiomeRegions,
+    shape::IslandCollider,
+    solver::ConstraintSolver,
     TILE_SIZE,
 };
-use super::{loader::SeaHandles, map::TileKind};
 use bevy::{
     prelude::*,
     render::pipeline::PrimitiveTopology,
-    sprite::TextureAtlas,
+    sprite::{Rect, TextureAtlas},
+    tasks::{AsyncComputeTaskPool, Task},
     utils::{HashMap, HashSet},
 };
+use futures_lite::future;
 use noise::{Fbm, MultiFractal, NoiseFn, Seedable};
-use parry2d::{na::Point2, shape::TriMesh};
 use seahash::SeaHasher;
 use serde::{Deserialize, Serialize};
 use std::{
     cmp::{max, min},
     collections::VecDeque,
     hash::Hasher,
-    ops::{Index, IndexMut},
     sync::Arc,
 };
 //bisous <3
@@ -29,76 +33,31 @@ use std::{
 pub struct SeaWorldGenPlugin;
 impl Plugin for SeaWorldGenPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.init_resource::<HashMap<IslandPos, Island>>()
-            .init_resource::<IslandQueue>()
+        app.init_resource::<IslandQueue>()
+            .init_resource::<WorldGenerator>()
+            .on_state_enter(GameState::STAGE, GameState::Sea, setup.system())
             .on_state_update(GameState::STAGE, GameState::Sea, worldgen_system.system());
     }
 }
 
-//The sprite ids in the sheet for every tile
-const SEA: i32 = -1;
+//The generator of the world, shared by everything that needs to know where the land is.
+//It is made when the game starts, once the seed is chosen.
+#[derive(Default, Clone)]
+pub struct WorldGenerator(pub Option<Arc<IslandGenerator>>);
 
-const FOREST_SEA_NW: i32 = 0;
-const FOREST_SEA_NE: i32 = 4;
-const FOREST_SEA_SE: i32 = 8;
-const FOREST_SEA_SW: i32 = 12;
-
-const FOREST_SEA_N: i32 = 16;
-const FOREST_SEA_E: i32 = 20;
-const FOREST_SEA_S: i32 = 24;
-const FOREST_SEA_W: i32 = 28;
-
-const FOREST_SEA_INNER_NW: i32 = 32;
-const FOREST_SEA_INNER_NE: i32 = 36;
-const FOREST_SEA_INNER_SE: i32 = 40;
-const FOREST_SEA_INNER_SW: i32 = 44;
-
-const FOREST_SEA_NESW: i32 = 48;
-const FOREST_SEA_NWSE: i32 = 52;
-
-const FOREST_SAND_NW: i32 = 56;
-const FOREST_SAND_NE: i32 = 60;
-const FOREST_SAND_SE: i32 = 64;
-const FOREST_SAND_SW: i32 = 68;
-
-const FOREST_SAND_N: i32 = 72;
-const FOREST_SAND_E: i32 = 76;
-const FOREST_SAND_S: i32 = 80;
-const FOREST_SAND_W: i32 = 84;
-
-const FOREST_SAND_INNER_NW: i32 = 88;
-const FOREST_SAND_INNER_NE: i32 = 92;
-const FOREST_SAND_INNER_SE: i32 = 96;
-const FOREST_SAND_INNER_SW: i32 = 100;
-
-const FOREST_SAND_NESW: i32 = 104;
-const FOREST_SAND_NWSE: i32 = 108;
-
-const FOREST: i32 = 112;
-
-const SAND_ROCK: i32 = 120;
-
-const SAND_SEA_NW: i32 = 124;
-const SAND_SEA_NE: i32 = 128;
-const SAND_SEA_SE: i32 = 132;
-const SAND_SEA_SW: i32 = 136;
-
-const SAND_SEA_N: i32 = 140;
-const SAND_SEA_E: i32 = 144;
-const SAND_SEA_S: i32 = 148;
-const SAND_SEA_W: i32 = 152;
-
-const SAND_SEA_INNER_NW: i32 = 156;
-const SAND_SEA_INNER_NE: i32 = 160;
-const SAND_SEA_INNER_SE: i32 = 164;
-const SAND_SEA_INNER_SW: i32 = 168;
-
-const SAND_SEA_NESW: i32 = 172;
-const SAND_SEA_NWSE: i32 = 176;
-
-const SAND: i32 = 180;
-
-const SEA_ROCK: i32 = 184;
+fn setup(
+    hasher: Res<SeededHasher>,
+    biomes: Res<BiomeConfig>,
+    mut generator: ResMut<WorldGenerator>,
+) {
+    if generator.0.is_some() {
+        return;
+    }
+    generator.0 = Some(Arc::new(IslandGenerator::new(
+        hasher.get_hasher(),
+        biomes.0.iter().map(|(_, biome)| biome.clone()).collect(),
+    )));
+}
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct GenerationParameters {
@@ -109,351 +68,473 @@ pub struct GenerationParameters {
     pub sea_level: f32,
     pub high_level: f32,
 }
+//How the sprites of the islands are chosen from the generated terrain
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum TileResolver {
+    //the autotiling rules of the tileset, the first pass removes the tiles they can't handle.
+    Rules,
+    //a constraint solver over the corners of the sprites, no terrain is lost.
+    Constraints,
+}
+impl Default for TileResolver {
+    fn default() -> Self {
+        TileResolver::Rules
+    }
+}
+//A sprite sheet cut in a grid of tiles, the sprites are numbered row by row.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SheetConfig {
+    pub path: String,
+    pub tile_size: f32,
+    pub columns: usize,
+    pub rows: usize,
+    //space between two tiles of the sheet
+    #[serde(default)]
+    pub padding: f32,
+}
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Biome {
     pub generation_parameters: GenerationParameters,
     pub name: String,
-    pub sea_sheet: String,
-    pub land_sheet: String,
+    //sprites of the islands seen from the sea, and of the island the player walks on
+    pub sea_sheet: SheetConfig,
+    pub land_sheet: SheetConfig,
+    //path to the ron file holding the autotiling rules of the sheet
+    pub tileset: String,
+    #[serde(default)]
+    pub resolver: TileResolver,
     pub weight: u32,
+    #[serde(skip)]
+    pub tile_set: Arc<TileSet>,
 }
-//The world generator only decide which tile type must be used at each coordinate (among sand, sea and forest here)
-//This ugly function use the surrounding tiles to determine which sprite must be displayed.
-//It still doesn't cover all cases.
-//I'm considering using a contraints solver instead.
-fn get_sprite_id(surroundings: [TileKind; 9], variant: u32) -> (u32, CollisionType) {
-    use CollisionType::*;
-    let half_var = variant / 2;
-    //Most tiles only have 4 variants, but some have 8. For those who have 8 it should use variant, for the other half_var
-    let (id, collision) = match surroundings {
-        [Sea(true), _, _, _, _, _, _, _, _] => (SEA_ROCK, Rigid),
-        [Sand(true), _, _, _, _, _, _, _, _] => (SAND_ROCK, Rigid),
-
-        //double corners
-        [Forest, Forest, _, Forest, Sea(_), Forest, _, Forest, Sea(_)] => (FOREST_SEA_NESW, Rigid),
-        [Forest, Forest, Sea(_), Forest, _, Forest, Sea(_), Forest, _] => (FOREST_SEA_NWSE, Rigid),
-
-        [Forest, Forest, _, Forest, Sand(_), Forest, _, Forest, Sand(_)] => {
-            (FOREST_SAND_NESW, Rigid)
-        }
-        [Forest, Forest, Sand(_), Forest, _, Forest, Sand(_), Forest, _] => {
-            (FOREST_SAND_NWSE, Rigid)
-        }
-
-        [Sand(_), Sand(_), _, Sand(_), Sea(_), Sand(_), _, Sand(_), Sea(_)] => {
-            (SAND_SEA_NESW, Friction)
-        }
-        [Sand(_), Sand(_), Sea(_), Sand(_), _, Sand(_), Sea(_), Sand(_), _] => {
-            (SAND_SEA_NWSE, Friction)
-        }
-        //outer corners
-        [Sea(_), _, _, _, _, _, _, _, _] => (SEA - half_var as i32, None),
-        [Forest, Sea(_), _, Forest, _, Forest, _, Sea(_), _]
-        | [Forest, Forest, Sea(_), Forest, _, Forest, _, Sea(_), Sea(_)]
-        | [Forest, Sea(_), _, Forest, _, Forest, Sea(_), Forest, Sea(_)] => (FOREST_SEA_NW, Rigid), //NW
-        [Forest, Sea(_), _, Sea(_), _, Forest, _, Forest, _]
-        | [Forest, Sea(_), Sea(_), Forest, Sea(_), Forest, _, Forest, _]
-        | [Forest, Forest, Sea(_), Sea(_), _, Forest, _, Forest, Sea(_)] => (FOREST_SEA_NE, Rigid), //NE
-        [Forest, Forest, _, Sea(_), _, Sea(_), _, Forest, _]
-        | [Forest, Forest, _, Sea(_), Sea(_), Forest, Sea(_), Forest, _]
-        | [Forest, Forest, Sea(_), Forest, Sea(_), Sea(_), _, Forest, _] => (FOREST_SEA_SE, Rigid), //SE
-        [Forest, Forest, _, Forest, _, Sea(_), _, Sea(_), _]
-        | [Forest, Forest, _, Forest, _, Sea(_), Sea(_), Forest, Sea(_)]
-        | [Forest, Forest, _, Forest, Sea(_), Forest, Sea(_), Sea(_), _] => (FOREST_SEA_SW, Rigid), //SW
-
-        [Forest, Sand(_), _, Forest, _, Forest, _, Sand(_), _]
-        | [Forest, Forest, Sand(_), Forest, _, Forest, _, Sand(_), Sand(_)]
-        | [Forest, Sand(_), _, Forest, _, Forest, Sand(_), Forest, Sand(_)] => {
-            (FOREST_SAND_NW, Rigid)
-        } //NW
-        [Forest, Sand(_), _, Sand(_), _, Forest, _, Forest, _]
-        | [Forest, Sand(_), Sand(_), Forest, Sand(_), Forest, _, Forest, _]
-        | [Forest, Forest, Sand(_), Sand(_), _, Forest, _, Forest, Sand(_)] => {
-            (FOREST_SAND_NE, Rigid)
-        } //NE
-        [Forest, Forest, _, Sand(_), _, Sand(_), _, Forest, _]
-        | [Forest, Forest, _, Sand(_), Sand(_), Forest, Sand(_), Forest, _]
-        | [Forest, Forest, Sand(_), Forest, Sand(_), Sand(_), _, Forest, _] => {
-            (FOREST_SAND_SE, Rigid)
-        } //SE
-        [Forest, Forest, _, Forest, _, Sand(_), _, Sand(_), _]
-        | [Forest, Forest, _, Forest, _, Sand(_), Sand(_), Forest, Sand(_)]
-        | [Forest, Forest, _, Forest, Sand(_), Forest, Sand(_), Sand(_), _] => {
-            (FOREST_SAND_SW, Rigid)
-        } //SW
-
-        [Sand(_), Sea(_), _, Sand(_), _, Sand(_), _, Sea(_), _]
-        | [Sand(_), Sand(_), Sea(_), Sand(_), _, Sand(_), _, Sea(_), Sea(_)]
-        | [Sand(_), Sea(_), _, Sand(_), _, Sand(_), Sea(_), Sand(_), Sea(_)] => {
-            (SAND_SEA_NW, Friction)
-        } //NW
-        [Sand(_), Sea(_), _, Sea(_), _, Sand(_), _, Sand(_), _]
-        | [Sand(_), Sea(_), Sea(_), Sand(_), Sea(_), Sand(_), _, Sand(_), _]
-        | [Sand(_), Sand(_), Sea(_), Sea(_), _, Sand(_), _, Sand(_), Sea(_)] => {
-            (SAND_SEA_NE, Friction)
-        } //NE
-        [Sand(_), Sand(_), _, Sea(_), _, Sea(_), _, Sand(_), _]
-        | [Sand(_), Sand(_), _, Sea(_), Sea(_), Sand(_), Sea(_), Sand(_), _]
-        | [Sand(_), Sand(_), Sea(_), Sand(_), Sea(_), Sea(_), _, Sand(_), _] => {
-            (SAND_SEA_SE, Friction)
-        } //SE
-        [Sand(_), Sand(_), _, Sand(_), _, Sea(_), _, Sea(_), _]
-        | [Sand(_), Sand(_), _, Sand(_), _, Sea(_), Sea(_), Sand(_), Sea(_)]
-        | [Sand(_), Sand(_), _, Sand(_), Sea(_), Sand(_), Sea(_), Sea(_), _] => {
-            (SAND_SEA_SW, Friction)
-        } //SW
-
-        //sides
-        [Forest, Sea(_), _, Forest, _, _, _, Forest, _]
-        | [Forest, Forest, Sea(_), Forest, _, _, _, Forest, Sea(_)] => (FOREST_SEA_N, Rigid), //N
-        [Forest, Forest, _, Sea(_), _, Forest, _, _, _]
-        | [Forest, Forest, Sea(_), Forest, Sea(_), Forest, _, _, _] => (FOREST_SEA_E, Rigid), //E
-        [Forest, _, _, Forest, _, Sea(_), _, Forest, _]
-        | [Forest, _, _, Forest, Sea(_), Forest, Sea(_), Forest, _] => (FOREST_SEA_S, Rigid), //S
-        [Forest, Forest, _, _, _, Forest, _, Sea(_), _]
-        | [Forest, Forest, _, _, _, Forest, Sea(_), Forest, Sea(_)] => (FOREST_SEA_W, Rigid), //W
-
-        [Forest, Sand(_), _, Forest, _, _, _, Forest, _]
-        | [Forest, Forest, Sand(_), Forest, _, _, _, Forest, Sand(_)] => (FOREST_SAND_N, Rigid), //N
-        [Forest, Forest, _, Sand(_), _, Forest, _, _, _]
-        | [Forest, Forest, Sand(_), Forest, Sand(_), Forest, _, _, _] => (FOREST_SAND_E, Rigid), //E
-        [Forest, _, _, Forest, _, Sand(_), _, Forest, _]
-        | [Forest, _, _, Forest, Sand(_), Forest, Sand(_), Forest, _] => (FOREST_SAND_S, Rigid), //S
-        [Forest, Forest, _, _, _, Forest, _, Sand(_), _]
-        | [Forest, Forest, _, _, _, Forest, Sand(_), Forest, Sand(_)] => (FOREST_SAND_W, Rigid), //W
-
-        [Sand(_), Sea(_), _, Sand(_), _, _, _, Sand(_), _]
-        | [Sand(_), Sand(_), Sea(_), Sand(_), _, _, _, Sand(_), Sea(_)] => (SAND_SEA_N, Friction), //N
-        [Sand(_), Sand(_), _, Sea(_), _, Sand(_), _, _, _]
-        | [Sand(_), Sand(_), Sea(_), Sand(_), Sea(_), Sand(_), _, _, _] => (SAND_SEA_E, Friction), //E
-        [Sand(_), _, _, Sand(_), _, Sea(_), _, Sand(_), _]
-        | [Sand(_), _, _, Sand(_), Sea(_), Sand(_), Sea(_), Sand(_), _] => (SAND_SEA_S, Friction), //S
-        [Sand(_), Sand(_), _, _, _, Sand(_), _, Sea(_), _]
-        | [Sand(_), Sand(_), _, _, _, Sand(_), Sea(_), Sand(_), Sea(_)] => (SAND_SEA_W, Friction), //W
-
-        //inner corners
-        [Forest, Forest, _, Forest, Sea(_), Forest, _, Forest, _] => (FOREST_SEA_INNER_NW, Rigid),
-        [Forest, Forest, _, Forest, _, Forest, Sea(_), Forest, _] => (FOREST_SEA_INNER_NE, Rigid),
-        [Forest, Forest, _, Forest, _, Forest, _, Forest, Sea(_)] => (FOREST_SEA_INNER_SE, Rigid),
-        [Forest, Forest, Sea(_), Forest, _, Forest, _, Forest, _] => (FOREST_SEA_INNER_SW, Rigid),
-
-        [Forest, Forest, _, Forest, Sand(_), Forest, _, Forest, _] => (FOREST_SAND_INNER_NW, Rigid),
-        [Forest, Forest, _, Forest, _, Forest, Sand(_), Forest, _] => (FOREST_SAND_INNER_NE, Rigid),
-        [Forest, Forest, _, Forest, _, Forest, _, Forest, Sand(_)] => (FOREST_SAND_INNER_SE, Rigid),
-        [Forest, Forest, Sand(_), Forest, _, Forest, _, Forest, _] => (FOREST_SAND_INNER_SW, Rigid),
-
-        [Sand(_), _, _, Sand(_), Sea(_), Sand(_), _, _, _] => (SAND_SEA_INNER_NW, Friction),
-        [Sand(_), _, _, _, _, Sand(_), Sea(_), Sand(_), _] => (SAND_SEA_INNER_NE, Friction),
-        [Sand(_), Sand(_), _, _, _, _, _, Sand(_), Sea(_)] => (SAND_SEA_INNER_SE, Friction),
-        [Sand(_), Sand(_), Sea(_), Sand(_), _, _, _, _, _] => (SAND_SEA_INNER_SW, Friction),
-
-        //triple
-        [_, Sand(_), Sand(_), Sand(_), Sand(_), Sand(_), _, _, _]
-        | [_, _, _, Sand(_), Sand(_), Sand(_), Sand(_), Sand(_), _]
-        | [_, Sand(_), _, _, _, Sand(_), Sand(_), Sand(_), Sand(_)]
-        | [_, Sand(_), Sand(_), Sand(_), _, _, _, Sand(_), Sand(_)] => (SAND, Rigid),
-
-        [_, Sea(_), Sea(_), Sea(_), Sea(_), Sea(_), _, _, _]
-        | [_, _, _, Sea(_), Sea(_), Sea(_), Sea(_), Sea(_), _]
-        | [_, Sea(_), _, _, _, Sea(_), Sea(_), Sea(_), Sea(_)]
-        | [_, Sea(_), Sea(_), Sea(_), _, _, _, Sea(_), Sea(_)] => (SEA - half_var as i32, None),
-
-        //inside
-        [Forest, Forest, _, Forest, _, Forest, _, Forest, _] => {
-            (FOREST - half_var as i32 + variant as i32, Rigid)
-        }
-        [Sand(_), x, _, y, _, z, _, w, _]
-            if x != Sea(false)
-                && y != Sea(false)
-                && z != Sea(false)
-                && w != Sea(false)
-                && x != Sea(true)
-                && y != Sea(true)
-                && z != Sea(true)
-                && w != Sea(true) =>
-        {
-            (SAND, Rigid)
-        }
-
-        _ => (SEA - half_var as i32, None),
-    };
-    (half_var + (1 + id) as u32, collision)
-}
-
-//Select a biome using the hasher (pre-loaded with the chunk coordinates) and the list of biomes
-pub fn select_biome(
-    mut hasher: SeaHasher,
-    BiomeConfig(config): BiomeConfig,
-) -> (Handle<TextureAtlas>, Biome) {
+//Select a biome using the hasher (pre-loaded with the region coordinates) and the list of biomes
+//Returns the index of the biome in the config.
+pub fn select_biome(mut hasher: SeaHasher, biomes: &[Biome]) -> usize {
     hasher.write_u64(0xB107E); //write a constant to change the number.
-    let total = config.iter().fold(0, |i, (_h, b)| i + b.weight);
+    let total = biomes.iter().fold(0, |i, b| i + b.weight);
     let hash = hasher.finish() as u32 % total;
     let mut temp_sum = 0;
-    for (h, b) in config.iter() {
+    for (i, b) in biomes.iter().enumerate() {
         temp_sum += b.weight;
-        if temp_sum >= hash {
-            return (h.clone(), b.clone());
+        if hash < temp_sum {
+            return i;
         }
     }
-    config[0].clone()
+    0
 }
 
-const VIEW_DISTANCE: i32 = 50;
-#[derive(Default, Debug)]
-struct Ribbon {
-    neg: Vec<(i32, i32)>,
-    pos: Vec<(i32, i32)>,
-}
-impl Index<i32> for Ribbon {
-    type Output = (i32, i32);
-
-    fn index(&self, index: i32) -> &Self::Output {
-        if index < 0 {
-            &self.neg[(-(index + 1)) as usize]
-        } else {
-            &self.pos[index as usize]
-        }
-    }
+//The world is streamed by square chunks of tiles around the player.
+pub const CHUNK_SIZE: i32 = 32;
+//chunks up to that distance of the player's chunk are generated
+const CHUNK_VIEW_DISTANCE: i32 = 2;
+//chunks further than that are forgotten, the islands they found are kept.
+const CHUNK_EVICT_DISTANCE: i32 = 4;
+//islands further than that many tiles are dropped from memory.
+//By then all their chunks are forgotten, so they are generated again when the player comes back.
+pub const ISLAND_EVICT_DISTANCE: i32 = (CHUNK_EVICT_DISTANCE + 2) * CHUNK_SIZE;
+//chunks are generated on the AsyncComputeTaskPool, at most that many at the same time.
+const MAX_CHUNK_TASKS: usize = 4;
+//islands added to the world each frame, the rest waits for the next frames.
+const ISLANDS_PER_FRAME: usize = 2;
+
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub struct ChunkPos {
+    pub x: i32,
+    pub y: i32,
 }
-impl IndexMut<i32> for Ribbon {
-    fn index_mut(&mut self, index: i32) -> &mut Self::Output {
-        if index < 0 {
-            &mut self.neg[(-(index + 1)) as usize]
-        } else {
-            &mut self.pos[index as usize]
+impl ChunkPos {
+    pub fn of((x, y): (i32, i32)) -> Self {
+        ChunkPos {
+            x: x.div_euclid(CHUNK_SIZE),
+            y: y.div_euclid(CHUNK_SIZE),
         }
     }
-}
-impl Ribbon {
-    // fn iter<'a>(&'a self) -> impl Iterator + 'a {
-    //     self.neg.iter().rev().chain(self.pos.iter())
-    // }
-    // fn iter_mut<'a>(&'a mut self) -> impl Iterator + 'a {
-    //     self.neg.iter_mut().rev().chain(self.pos.iter_mut())
-    // }
-    fn iter_mut_enumerate<'a>(&'a mut self) -> impl Iterator<Item = (i32, &mut (i32, i32))> + 'a {
-        let low_bound = -(self.neg.len() as i32);
-        let high_bound = self.pos.len() as i32;
-        (low_bound..high_bound)
-            .into_iter()
-            .zip(self.neg.iter_mut().rev().chain(self.pos.iter_mut()))
-    }
-    fn len_pos(&self) -> i32 {
-        self.pos.len() as i32
+
+    pub fn rect(&self) -> TileRect {
+        TileRect::new(
+            (self.x * CHUNK_SIZE, self.y * CHUNK_SIZE),
+            ((self.x + 1) * CHUNK_SIZE, (self.y + 1) * CHUNK_SIZE),
+        )
     }
-    fn len_neg(&self) -> i32 {
-        -(self.neg.len() as i32)
+
+    //number of chunks between the two, diagonals included
+    pub fn distance(&self, other: ChunkPos) -> i32 {
+        (self.x - other.x).abs().max((self.y - other.y).abs())
     }
-    fn expand_pos(&mut self, y_value: i32) {
-        self.pos.push((y_value, y_value + 1))
+}
+#[derive(Default)]
+struct Chunk {
+    generated: bool,
+    //tiles already part of an island found from another chunk
+    covered: HashSet<(i32, i32)>,
+}
+//islands found by a chunk task, with their mesh and the tiles looked at by their flood fill
+type ChunkIslands = Vec<(Island, Mesh, Vec<(i32, i32)>)>;
+#[derive(Default)]
+struct WorldChunks {
+    //the sea sheet of every biome, the tasks build the meshes with them
+    layouts: Option<Arc<Vec<AtlasLayout>>>,
+    chunks: HashMap<ChunkPos, Chunk>,
+    tasks: Vec<(ChunkPos, Task<ChunkIslands>)>,
+    //islands waiting to be added to the world
+    ready: VecDeque<(Island, Mesh)>,
+}
+//A rectangle of tiles, min included and max excluded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TileRect {
+    pub min: (i32, i32),
+    pub max: (i32, i32),
+}
+impl TileRect {
+    pub fn new(min: (i32, i32), max: (i32, i32)) -> Self {
+        TileRect { min, max }
     }
-    fn expand_neg(&mut self, y_value: i32) {
-        self.neg.push((y_value, y_value + 1))
+
+    pub fn tiles(&self) -> impl Iterator<Item = (i32, i32)> {
+        let (min, max) = (self.min, self.max);
+        (min.0..max.0).flat_map(move |x| (min.1..max.1).map(move |y| (x, y)))
     }
 }
-struct GenRessources {
-    pub noise: Fbm,
-    pub hasher: SeaHasher,
-    pub biome: Biome,
+
+//Generates the islands of a world from a seed and a list of biomes.
+//It doesn't use anything from the ECS, the game keeps one in worldgen_system and tools can make their own.
+pub struct IslandGenerator {
+    //one noise per biome, they all share the same seed so they can be blended
+    noises: Vec<Fbm>,
+    hasher: SeaHasher,
+    biomes: Arc<Vec<Biome>>,
+    regions: BiomeRegions,
 }
-impl FromResources for GenRessources {
-    fn from_resources(resources: &Resources) -> Self {
-        let config = resources.get::<BiomeConfig>().unwrap();
-        let mut hasher = resources.get::<SeededHasher>().unwrap().get_hasher();
-        let noise = noise::Fbm::new();
+impl IslandGenerator {
+    //the hasher must be seeded already, like the one of SeededHasher.
+    //The biomes must have their tile_set loaded.
+    pub fn new(mut hasher: SeaHasher, biomes: Vec<Biome>) -> Self {
         hasher.write(&*"sea_island_gen".to_string().into_bytes());
         let hasher = hasher; //prevent mutability
-        let (_handle, biome) = select_biome(hasher, (&*config).clone());
-        let noise = noise
-            .set_seed(hasher.finish() as u32)
-            .set_octaves(biome.generation_parameters.octaves)
-            .set_lacunarity(biome.generation_parameters.lacunarity)
-            .set_persistence(biome.generation_parameters.persistence)
-            .set_frequency(biome.generation_parameters.frequency);
+        let noises = biomes
+            .iter()
+            .map(|biome| {
+                noise::Fbm::new()
+                    .set_seed(hasher.finish() as u32)
+                    .set_octaves(biome.generation_parameters.octaves)
+                    .set_lacunarity(biome.generation_parameters.lacunarity)
+                    .set_persistence(biome.generation_parameters.persistence)
+                    .set_frequency(biome.generation_parameters.frequency)
+            })
+            .collect();
+        let biomes = Arc::new(biomes);
         Self {
-            noise,
+            noises,
             hasher,
-            biome,
+            regions: BiomeRegions::new(hasher, biomes.clone()),
+            biomes,
         }
     }
+
+    pub fn from_seed(seed: &str, biomes: Vec<Biome>) -> Self {
+        Self::new(SeededHasher::new(seed).get_hasher(), biomes)
+    }
+
+    //a world made of a single biome
+    pub fn with_biome(seed: &str, biome: Biome) -> Self {
+        Self::from_seed(seed, vec![biome])
+    }
 }
 
-fn get_height(noise: &Fbm, (x, y): (i32, i32)) -> f64 {
-    noise.get([x as f64, y as f64])
+//The terrain at a tile. Near the border of a region, it is blended with the neighbour biome.
+struct TerrainSample {
+    height: f64,
+    sea_level: f64,
+    high_level: f64,
 }
-#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
-pub struct IslandPos {
-    pub x: (i32, i32),
-    pub y: (i32, i32),
+impl IslandGenerator {
+    pub fn biome(&self, id: usize) -> &Biome {
+        &self.biomes[id]
+    }
+
+    fn sample(&self, (x, y): (i32, i32)) -> TerrainSample {
+        let terrain = |biome: usize| {
+            let parameters = &self.biome(biome).generation_parameters;
+            TerrainSample {
+                height: self.noises[biome].get([x as f64, y as f64]),
+                sea_level: parameters.sea_level as f64,
+                high_level: parameters.high_level as f64,
+            }
+        };
+        let region = self.regions.sample((x, y));
+        let sample = terrain(region.biome);
+        if region.blend <= 0. || region.neighbour == region.biome {
+            return sample;
+        }
+        let other = terrain(region.neighbour);
+        let blend = region.blend as f64;
+        TerrainSample {
+            height: sample.height * (1. - blend) + other.height * blend,
+            sea_level: sample.sea_level * (1. - blend) + other.sea_level * blend,
+            high_level: sample.high_level * (1. - blend) + other.high_level * blend,
+        }
+    }
+
+    pub fn is_land(&self, tile: (i32, i32)) -> bool {
+        let sample = self.sample(tile);
+        sample.height >= sample.sea_level
+    }
+
+    //Generates every island having at least one tile in the rectangle.
+    //The islands are whole, even the parts outside of the rectangle.
+    pub fn generate_islands(&self, rect: TileRect) -> Vec<Island> {
+        self.generate_islands_skipping(rect, &HashSet::default())
+            .into_iter()
+            .map(|(island, _)| island)
+            .collect()
+    }
+
+    //Same as generate_islands, but the tiles in covered are known to belong to other islands.
+    //Every island comes with the tiles looked at by its flood fill, sea included.
+    pub fn generate_islands_skipping(
+        &self,
+        rect: TileRect,
+        covered: &HashSet<(i32, i32)>,
+    ) -> Vec<(Island, Vec<(i32, i32)>)> {
+        let mut visited_tiles = HashSet::default();
+        let mut islands = Vec::new();
+        for tile in rect.tiles() {
+            if covered.contains(&tile) || visited_tiles.contains(&tile) {
+                continue;
+            }
+            let mut visited = vec![tile];
+            if let Some(island) = self.generate_island_visiting(tile, |t| visited.push(t)) {
+                visited_tiles.extend(visited.iter().copied());
+                islands.push((island, visited));
+            }
+        }
+        islands
+    }
+
+    //Generates the island the tile belongs to, None if the tile is sea.
+    pub fn generate_island(&self, tile: (i32, i32)) -> Option<Island> {
+        self.generate_island_visiting(tile, |_| ())
+    }
+
+    //Same as generate_island, visit is called on every tile looked at by the flood fill, sea included.
+    fn generate_island_visiting(
+        &self,
+        tile: (i32, i32),
+        mut visit: impl FnMut((i32, i32)),
+    ) -> Option<Island> {
+        let sample = self.sample(tile);
+        if sample.height < sample.sea_level {
+            return None;
+        }
+        let mut min_x = tile.0;
+        let mut max_x = tile.0;
+        let mut min_y = tile.1;
+        let mut max_y = tile.1;
+        let mut island_queue = VecDeque::new();
+        let mut tiles = HashMap::default();
+        tiles.insert(tile, Tile::new(self.hasher, &sample, tile));
+        island_queue.push_back(tile);
+        while let Some((x, y)) = island_queue.pop_front() {
+            max_y = max(max_y, y);
+            min_y = min(min_y, y);
+            max_x = max(max_x, x);
+            min_x = min(min_x, x);
+            for (nx, ny) in [
+                (x + 1, y),
+                (x - 1, y),
+                (x, y + 1),
+                (x, y - 1),
+                (x - 1, y + 1),
+                (x + 1, y + 1),
+                (x + 1, y - 1),
+                (x - 1, y - 1),
+            ]
+            .iter()
+            {
+                let (nx, ny) = (*nx, *ny);
+                //skips the already processed tiles
+                if tiles.contains_key(&(nx, ny)) {
+                    continue;
+                }
+                visit((nx, ny));
+                //if the tile is sea, skips it
+                let sample = self.sample((nx, ny));
+                if sample.height < sample.sea_level {
+                    continue;
+                }
+                tiles.insert((nx, ny), Tile::new(self.hasher, &sample, (nx, ny)));
+                island_queue.push_back((nx, ny))
+            }
+        }
+        let id = IslandId::new(self.hasher, *tiles.keys().min().unwrap());
+        let size_y = max_y - min_y + 1;
+        let size_x = max_x - min_x + 1;
+        let mut tiles_vec = vec![vec![Tile::default(); size_y as usize]; size_x as usize];
+        for ((x, y), t) in tiles.into_iter() {
+            tiles_vec[(x - min_x) as usize][(y - min_y) as usize] = t;
+        }
+        //the island takes the biome of the region its corner is in
+        let biome = self.regions.sample((min_x, min_y)).biome;
+        let collisions = resolve_tiles(
+            &mut tiles_vec,
+            self.biome(biome),
+            self.hasher,
+            (min_x, min_y),
+        );
+        let mask = |collision: CollisionType| -> Vec<Vec<bool>> {
+            collisions
+                .iter()
+                .map(|column| column.iter().map(|c| *c == collision).collect())
+                .collect()
+        };
+        let rigid_collider = IslandCollider::new(&mask(CollisionType::Rigid), TILE_SIZE as f32);
+        let friction_collider =
+            IslandCollider::new(&mask(CollisionType::Friction), TILE_SIZE as f32);
+        Some(Island {
+            id,
+            min_x,
+            max_x,
+            min_y,
+            max_y,
+            biome,
+            tiles: Arc::new(tiles_vec),
+            //the mesh depends on the atlas of the biome, it is built by whoever displays the island
+            mesh: Handle::default(),
+            entity: None,
+            rigid_collider,
+            friction_collider,
+            mobs: Vec::new(),
+            features_generated: false,
+        })
+    }
+}
+
+//Identifies an island whatever the route that led to it, it only depends on the seed and the island's tiles.
+#[derive(Debug, Default, Clone, Copy, Hash, Eq, PartialEq, Serialize, Deserialize)]
+pub struct IslandId(pub u64);
+impl IslandId {
+    //the canonical tile is the land tile with the lowest x, then the lowest y.
+    fn new(mut hasher: SeaHasher, canonical_tile: (i32, i32)) -> Self {
+        hasher.write(b"island_id");
+        hasher.write_i32(canonical_tile.0);
+        hasher.write_i32(canonical_tile.1);
+        IslandId(hasher.finish())
+    }
 }
 #[derive(Default)]
 pub struct IslandQueue(pub Vec<Island>);
 
 fn worldgen_system(
-    mut island_map: Local<HashSet<IslandPos>>,
+    islands: Res<Islands>,
     mut islands_to_add: ResMut<IslandQueue>,
     player_pos: Res<PlayerPositionUpdate>,
-    mut ribbon: Local<Ribbon>,
-    gen_ressources: Local<GenRessources>,
+    mut chunks: Local<WorldChunks>,
+    pool: Res<AsyncComputeTaskPool>,
     mut meshes: ResMut<Assets<Mesh>>,
     atlases: Res<Assets<TextureAtlas>>,
-    handles: Res<SeaHandles>,
+    biomes: Res<BiomeConfig>,
+    generator: Res<WorldGenerator>,
 ) {
-    let tile_size = Vec2::new(TILE_SIZE as f32, TILE_SIZE as f32);
-
-    if ribbon.len_pos() - player_pos.x <= VIEW_DISTANCE {
-        ribbon.expand_pos(player_pos.y)
+    let generator = match &generator.0 {
+        Some(generator) => generator,
+        None => return,
+    };
+    let chunks = &mut *chunks;
+    let layouts = chunks
+        .layouts
+        .get_or_insert_with(|| {
+            Arc::new(
+                biomes
+                    .0
+                    .iter()
+                    .map(|(assets, _)| {
+                        AtlasLayout::from(atlases.get(assets.sea_sheet.clone()).unwrap())
+                    })
+                    .collect(),
+            )
+        })
+        .clone();
+    let player_chunk = ChunkPos::of((player_pos.x, player_pos.y));
+    chunks
+        .chunks
+        .retain(|pos, _| pos.distance(player_chunk) <= CHUNK_EVICT_DISTANCE);
+    //the missing chunks, the closest first
+    let mut to_generate = Vec::new();
+    for x in -CHUNK_VIEW_DISTANCE..=CHUNK_VIEW_DISTANCE {
+        for y in -CHUNK_VIEW_DISTANCE..=CHUNK_VIEW_DISTANCE {
+            let pos = ChunkPos {
+                x: player_chunk.x + x,
+                y: player_chunk.y + y,
+            };
+            if !chunks
+                .chunks
+                .get(&pos)
+                .map(|c| c.generated)
+                .unwrap_or(false)
+            {
+                to_generate.push(pos);
+            }
+        }
     }
-    if player_pos.x - ribbon.len_neg() <= VIEW_DISTANCE {
-        ribbon.expand_neg(player_pos.y)
+    to_generate.sort_by_key(|pos| (pos.distance(player_chunk), pos.x, pos.y));
+    let free_tasks = MAX_CHUNK_TASKS.saturating_sub(chunks.tasks.len());
+    for pos in to_generate.into_iter().take(free_tasks) {
+        let chunk = chunks.chunks.entry(pos).or_default();
+        chunk.generated = true;
+        let covered = std::mem::take(&mut chunk.covered);
+        let generator = generator.clone();
+        let layouts = layouts.clone();
+        let task = pool.spawn(async move {
+            let tile_size = Vec2::new(TILE_SIZE as f32, TILE_SIZE as f32);
+            generator
+                .generate_islands_skipping(pos.rect(), &covered)
+                .into_iter()
+                .map(|(island, visited)| {
+                    let mesh = island_mesh(&island.tiles, &layouts[island.biome], tile_size);
+                    (island, mesh, visited)
+                })
+                .collect()
+        });
+        chunks.tasks.push((pos, task));
     }
-    let mut island_tiles = VecDeque::new();
-    for (i, (min, max)) in ribbon.iter_mut_enumerate() {
-        //if to far off horizontally, skips.
-        if (i - player_pos.x).abs() > VIEW_DISTANCE {
-            continue;
-        }
-        //if the player did a large circle for example, the ribbon can be very far.
-        //this discards the far segment and makes a new one closer.
-        //it just discards a bit of cache, but the generated islands are kept, no no big deal.
-        if *min - player_pos.y >= 2 * VIEW_DISTANCE || *max - player_pos.y >= 2 * VIEW_DISTANCE {
-            *min = player_pos.y;
-            *max = player_pos.y + 1;
-        }
-        //finally, enlarges the ribbon when necessary
-        if player_pos.y - *min <= VIEW_DISTANCE {
-            let height = get_height(&gen_ressources.noise, (i, *min));
-            if height >= gen_ressources.biome.generation_parameters.sea_level as f64 {
-                island_tiles.push_back((i, *min))
+    //collects the finished chunks
+    for (pos, mut task) in std::mem::take(&mut chunks.tasks) {
+        let islands = match future::block_on(future::poll_once(&mut task)) {
+            Some(islands) => islands,
+            None => {
+                chunks.tasks.push((pos, task));
+                continue;
             }
-            *min -= 1;
-        }
-        if *max - player_pos.y <= VIEW_DISTANCE {
-            let height = get_height(&gen_ressources.noise, (i, *max));
-            if height >= gen_ressources.biome.generation_parameters.sea_level as f64 {
-                island_tiles.push_back((i, *max))
+        };
+        for (island, mesh, visited) in islands {
+            //islands crossing the border are generated whole, the other chunks must not find them again.
+            for tile in visited {
+                let tile_chunk = ChunkPos::of(tile);
+                if tile_chunk != pos && tile_chunk.distance(player_chunk) <= CHUNK_EVICT_DISTANCE {
+                    let chunk = chunks.chunks.entry(tile_chunk).or_default();
+                    if !chunk.generated {
+                        chunk.covered.insert(tile);
+                    }
+                }
+            }
+            //a chunk coming back can find an island that was kept,
+            //and two chunks generated at the same time can both find an island crossing their border.
+            let known = islands.0.contains_key(&island.id)
+                || islands_to_add.0.iter().any(|i| i.id == island.id)
+                || chunks.ready.iter().any(|(i, _)| i.id == island.id);
+            if !known {
+                chunks.ready.push_back((island, mesh));
             }
-            *max += 1;
         }
     }
-    let mut processed_tiles = HashSet::default();
-    while let Some((x, y)) = island_tiles.pop_front() {
-        if !processed_tiles.insert((x, y)) {
-            continue;
-        }
-        if let Some(island) = generate_island(
-            (x, y),
-            &gen_ressources,
-            &mut island_tiles,
-            &mut processed_tiles,
-            &mut island_map,
-            &mut ribbon,
-            atlases.get(handles.islands_sheet.clone()).unwrap(),
-            &mut *meshes,
-            tile_size,
-        ) {
-            islands_to_add.0.push(island)
+    //a chunk can hold many islands, they are added a few at a time.
+    for _ in 0..ISLANDS_PER_FRAME {
+        let (mut island, mesh) = match chunks.ready.pop_front() {
+            Some(ready) => ready,
+            None => break,
         };
+        island.mesh = meshes.add(mesh);
+        islands_to_add.0.push(island)
     }
 }
 #[derive(Default, Clone, Copy)]
@@ -464,33 +545,38 @@ pub struct Tile {
 }
 
 impl Tile {
-    fn new(mut hasher: SeaHasher, height: f64, position: (i32, i32), biome: &Biome) -> Self {
+    fn new(mut hasher: SeaHasher, sample: &TerrainSample, position: (i32, i32)) -> Self {
         hasher.write_i32(position.0);
         hasher.write_i32(position.1);
         Self {
-            kind: if height < biome.generation_parameters.sea_level as f64 {
+            kind: if sample.height < sample.sea_level {
                 TileKind::Sea(false)
-            } else if height < biome.generation_parameters.high_level as f64 {
+            } else if sample.height < sample.high_level {
                 TileKind::Sand(false)
             } else {
                 TileKind::Forest
             },
-            variant: (hasher.finish() * 7 % 8) as u32,
+            variant: (hasher.finish() * 7 % TILE_VARIANTS as u64) as u32,
             sprite_id: None,
         }
     }
 }
 pub struct Island {
+    pub id: IslandId,
     pub tiles: Arc<Vec<Vec<Tile>>>,
     pub mesh: Handle<Mesh>,
     pub min_x: i32,
     pub max_x: i32,
     pub min_y: i32,
     pub max_y: i32,
+    //index of the biome in the BiomeConfig
+    pub biome: usize,
     pub entity: Option<Entity>,
-    pub rigid_trimesh: Option<TriMesh>,
-    pub friction_trimesh: Option<TriMesh>,
+    pub rigid_collider: Option<IslandCollider>,
+    pub friction_collider: Option<IslandCollider>,
     pub mobs: Vec<(Mob, Transform)>,
+    //the land module fills the island (mobs...) once
+    pub features_generated: bool,
 }
 fn get_surroundings(tiles_vec: &[Vec<Tile>], i: usize, j: usize) -> [TileKind; 9] {
     [
@@ -560,6 +646,52 @@ fn get_surroundings(tiles_vec: &[Vec<Tile>], i: usize, j: usize) -> [TileKind; 9
     ]
 }
 
+//The position of the sprites in a TextureAtlas, without the texture so it can be sent to another thread.
+#[derive(Debug, Clone)]
+pub struct AtlasLayout {
+    pub size: Vec2,
+    pub textures: Vec<Rect>,
+}
+impl From<&TextureAtlas> for AtlasLayout {
+    fn from(atlas: &TextureAtlas) -> Self {
+        AtlasLayout {
+            size: atlas.size,
+            textures: atlas.textures.clone(),
+        }
+    }
+}
+
+//Builds the mesh drawing the sprites of the tiles with the given atlas.
+pub fn island_mesh(tiles: &[Vec<Tile>], atlas: &AtlasLayout, tile_size: Vec2) -> Mesh {
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = Vec::new();
+    let mut indices = Vec::new();
+    let mut i = 0;
+    for (x, column) in tiles.iter().enumerate() {
+        for (y, tile) in column.iter().enumerate() {
+            add_tile_to_mesh(
+                tile_size,
+                tile.sprite_id.unwrap_or(0),
+                x,
+                y,
+                &mut positions,
+                &mut normals,
+                &mut uvs,
+                &mut indices,
+                atlas,
+                &mut i,
+            )
+        }
+    }
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.set_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.set_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh.set_indices(Some(bevy::render::mesh::Indices::U16(indices)));
+    mesh
+}
+
 fn add_tile_to_mesh(
     tile_size: Vec2,
     id: u32,
@@ -569,7 +701,7 @@ fn add_tile_to_mesh(
     normals: &mut Vec<[f32; 3]>,
     uvs: &mut Vec<[f32; 2]>,
     indices: &mut Vec<u16>,
-    atlas: &TextureAtlas,
+    atlas: &AtlasLayout,
     i: &mut usize,
 ) {
     if id == 0 {
@@ -615,148 +747,11 @@ fn add_tile_to_mesh(
     *i += 4;
 }
 
-fn add_tile_to_trimesh(
-    tile_size: Vec2,
-    x: usize,
-    y: usize,
-    positions: &mut Vec<Point2<f32>>,
-    indices: &mut Vec<[u32; 3]>,
-    i: &mut usize,
-) {
-    let tile_pos = {
-        let start = Vec2::new(x as f32 * tile_size.x, y as f32 * tile_size.y);
-
-        let end = Vec2::new((x + 1) as f32 * tile_size.x, (y + 1) as f32 * tile_size.y);
-        Vec4::new(end.x, end.y, start.x, start.y)
-    };
-    // X, Y
-    positions.push(Point2::new(tile_pos.x, tile_pos.y));
-
-    // X, Y + 1
-    positions.push(Point2::new(tile_pos.z, tile_pos.y));
-
-    // X + 1, Y + 1
-    positions.push(Point2::new(tile_pos.z, tile_pos.w));
-
-    // X + 1, Y
-    positions.push(Point2::new(tile_pos.x, tile_pos.w));
-    let j = *i as u32;
-    indices.push([j, j + 2, j + 1]);
-    indices.push([j, j + 3, j + 2]);
-    *i += 4;
-}
-fn generate_island(
-    tile: (i32, i32),
-    gen_ressources: &GenRessources,
-    to_process: &mut VecDeque<(i32, i32)>,
-    processed: &mut HashSet<(i32, i32)>,
-    generated_islands: &mut HashSet<IslandPos>,
-    ribbon: &mut Ribbon,
-    atlas: &TextureAtlas,
-    meshes: &mut Assets<Mesh>,
-    tile_size: Vec2,
-) -> Option<Island> {
-    let mut min_x = tile.0;
-    let mut max_x = tile.0;
-    let mut min_y = tile.1;
-    let mut max_y = tile.1;
-    let mut island_queue = VecDeque::new();
-    let mut tiles = HashMap::default();
-    island_queue.push_back(tile);
-    while let Some((x, y)) = island_queue.pop_front() {
-        max_y = max(max_y, y); //TODO: check orientation
-        min_y = min(min_y, y);
-        max_x = max(max_x, x);
-        min_x = min(min_x, x);
-        for (nx, ny) in [
-            (x + 1, y),
-            (x - 1, y),
-            (x, y + 1),
-            (x, y - 1),
-            (x - 1, y + 1),
-            (x + 1, y + 1),
-            (x + 1, y - 1),
-            (x - 1, y - 1),
-        ]
-        .iter()
-        {
-            let (nx, ny) = (*nx, *ny);
-            //skips the already processed tiles
-            if tiles.contains_key(&(nx, ny)) {
-                continue;
-            }
-            //updates the ribbon
-            processed.insert((nx, ny));
-            if nx >= ribbon.len_pos() {
-                ribbon.expand_pos(ny);
-                ribbon[nx] = (ny - 1, ny + 1) //should work because nx can only be one more than the max.
-            }
-            if nx <= ribbon.len_neg() {
-                ribbon.expand_neg(ny);
-                ribbon[nx] = (ny - 1, ny + 1) //shoukd work because nx can only be one more than the max.
-            }
-            let (min, max) = &mut ribbon[nx];
-            //if there is a gap that is too large, move the ribbon
-            if *min - ny >= 2 * VIEW_DISTANCE || ny - *max >= 2 * VIEW_DISTANCE {
-                *min = ny - 1;
-                *max = ny + 1;
-            }
-            //if there is a gap, add all tiles in between to be processed.
-            if ny <= *min {
-                //add all the tiles in between to be processed
-                for y in ny + 1..*min + 1 {
-                    let height = get_height(&gen_ressources.noise, (nx, y));
-                    if height >= gen_ressources.biome.generation_parameters.sea_level as f64 {
-                        to_process.push_back((nx, y));
-                    }
-                }
-                *min = ny - 1;
-            }
-            if ny >= *max {
-                //add all the tiles in between to be processed
-                for y in *max..ny {
-                    let height = get_height(&gen_ressources.noise, (nx, y));
-                    if height >= gen_ressources.biome.generation_parameters.sea_level as f64 {
-                        to_process.push_back((nx, y));
-                    }
-                }
-                *max = ny + 1;
-            }
-            //if the tile is sea, skips it
-            let height = get_height(&gen_ressources.noise, (nx, ny));
-            if height < gen_ressources.biome.generation_parameters.sea_level as f64 {
-                continue;
-            }
-            let tile = Tile::new(
-                gen_ressources.hasher,
-                height,
-                (nx, ny),
-                &gen_ressources.biome,
-            );
-            tiles.insert((nx, ny), tile);
-            island_queue.push_back((nx, ny))
-        }
-    }
-    let size_y = max_y - min_y + 1;
-    let size_x = max_x - min_x + 1;
-    if generated_islands.contains(&IslandPos {
-        x: (min_x, max_x),
-        y: (min_y, max_y),
-    }) {
-        return None;
-    }
-    generated_islands.insert(IslandPos {
-        x: (min_x, max_x),
-        y: (min_y, max_y),
-    });
-    let mut tiles_vec = vec![vec![Tile::default(); size_y as usize]; size_x as usize];
-    for ((x, y), t) in tiles.into_iter() {
-        tiles_vec[(x - min_x) as usize][(y - min_y) as usize] = t;
-    }
-    //do a first pass where some tiles are deleted to avoid causing problems
-    for i in 0..size_x as usize {
-        for j in 0..size_y as usize {
-            let surroundings = get_surroundings(&tiles_vec, i, j);
+//Some patterns have no sprite in the rules, so a first pass turns the problematic tiles into rocks.
+fn remove_unsolvable_tiles(tiles_vec: &mut [Vec<Tile>]) {
+    for i in 0..tiles_vec.len() {
+        for j in 0..tiles_vec[i].len() {
+            let surroundings = get_surroundings(tiles_vec, i, j);
             match surroundings {
                 [Forest, Sand(_), Sand(_), Sand(_), Sand(_), Sand(_), _, _, _]
                 | [Forest, _, _, Sand(_), Sand(_), Sand(_), Sand(_), Sand(_), _]
@@ -775,86 +770,155 @@ fn generate_island(
             }
         }
     }
-    let mut rigid_positions = Vec::new(); //everything that must be constructed
-    let mut rigid_indices = Vec::new();
-    let mut rigid_i = 0;
-    let mut friction_positions = Vec::new();
-    let mut friction_indices = Vec::new();
-    let mut friction_i = 0;
-    let mut positions = Vec::new();
-    let mut normals = Vec::new();
-    let mut uvs = Vec::new();
-    let mut indices = Vec::new();
-    let mut i = 0;
-    //then complete the sprite ids
-    for x in 0..size_x as usize {
-        for y in 0..size_y as usize {
-            let surroundings = get_surroundings(&tiles_vec, x, y);
-            let tile = &mut tiles_vec[x][y];
-            let (sprite_id, collision_type) = get_sprite_id(surroundings, tile.variant);
-            tile.sprite_id = Some(sprite_id);
-            match collision_type {
-                CollisionType::Friction => add_tile_to_trimesh(
-                    tile_size,
-                    x,
-                    y,
-                    &mut friction_positions,
-                    &mut friction_indices,
-                    &mut friction_i,
-                ),
-                CollisionType::None => {
-                    continue;
-                }
-                CollisionType::Rigid => {
-                    add_tile_to_trimesh(
-                        tile_size,
-                        x,
-                        y,
-                        &mut rigid_positions,
-                        &mut rigid_indices,
-                        &mut rigid_i,
-                    );
+}
+
+//Fills the sprite ids of the tiles using the resolver of the biome, and returns their collision types.
+//If the constraint solver gives up, the rules are used instead.
+fn resolve_tiles(
+    tiles_vec: &mut [Vec<Tile>],
+    biome: &Biome,
+    hasher: SeaHasher,
+    (min_x, min_y): (i32, i32),
+) -> Vec<Vec<CollisionType>> {
+    let mut collisions: Vec<Vec<CollisionType>> = tiles_vec
+        .iter()
+        .map(|v| vec![CollisionType::None; v.len()])
+        .collect();
+    if biome.resolver == TileResolver::Constraints {
+        let kinds: Vec<Vec<TileKind>> = tiles_vec
+            .iter()
+            .map(|v| v.iter().map(|t| t.kind).collect())
+            .collect();
+        let mut hasher = hasher;
+        hasher.write_i32(min_x);
+        hasher.write_i32(min_y);
+        let solved = ConstraintSolver::new(&biome.tile_set.wang_tiles)
+            .and_then(|solver| solver.solve(&kinds, hasher).map(|ids| (solver, ids)));
+        if let Some((solver, ids)) = solved {
+            for (x, column) in ids.iter().enumerate() {
+                for (y, id) in column.iter().enumerate() {
+                    let wang_tile = solver.tile(*id);
+                    let tile = &mut tiles_vec[x][y];
+                    //fixing a contradiction can move the terrain a bit, the kind must follow what is displayed
+                    if !wang_tile.corners.contains(&Terrain::of(tile.kind)) {
+                        tile.kind = wang_tile.main_terrain().kind();
+                    }
+                    tile.sprite_id = Some(sprite_id(
+                        wang_tile.sprite,
+                        wang_tile.variants,
+                        tile.variant,
+                    ));
+                    collisions[x][y] = wang_tile.collision;
                 }
             }
-            add_tile_to_mesh(
-                tile_size,
-                sprite_id,
-                x,
-                y,
-                &mut positions,
-                &mut normals,
-                &mut uvs,
-                &mut indices,
-                atlas,
-                &mut i,
+            return collisions;
+        }
+        warn!(
+            "the constraint solver gave up on the island at ({}, {}), using the rules of the {} tileset",
+            min_x, min_y, biome.name
+        );
+    }
+    remove_unsolvable_tiles(tiles_vec);
+    let mut unmatched = Vec::new();
+    for x in 0..tiles_vec.len() {
+        for y in 0..tiles_vec[x].len() {
+            let surroundings = get_surroundings(tiles_vec, x, y);
+            let tile = &mut tiles_vec[x][y];
+            let resolved = biome.tile_set.resolve(surroundings, tile.variant);
+            if resolved.fallback {
+                unmatched.push(surroundings);
+            }
+            tile.sprite_id = Some(resolved.sprite_id);
+            collisions[x][y] = resolved.collision;
+        }
+    }
+    if let Some(surroundings) = unmatched.first() {
+        warn!(
+            "{} tiles of the island at ({}, {}) matched no rule of the {} tileset, first one was {:?}",
+            unmatched.len(),
+            min_x,
+            min_y,
+            biome.name,
+            surroundings
+        );
+    }
+    collisions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sea::loader::read_worldgen_config;
+
+    type IslandSummary = (
+        u64,
+        (i32, i32, i32, i32),
+        usize,
+        Vec<Vec<(TileKind, Option<u32>)>>,
+    );
+
+    //what must not change between two generations
+    fn summary(islands: &[Island]) -> Vec<IslandSummary> {
+        let mut summary: Vec<_> = islands
+            .iter()
+            .map(|island| {
+                (
+                    island.id.0,
+                    (island.min_x, island.max_x, island.min_y, island.max_y),
+                    island.biome,
+                    island
+                        .tiles
+                        .iter()
+                        .map(|column| {
+                            column
+                                .iter()
+                                .map(|tile| (tile.kind, tile.sprite_id))
+                                .collect()
+                        })
+                        .collect(),
+                )
+            })
+            .collect();
+        summary.sort_by_key(|(id, ..)| *id);
+        summary
+    }
+
+    fn generate(seed: &str) -> Vec<Island> {
+        IslandGenerator::from_seed(seed, read_worldgen_config())
+            .generate_islands(TileRect::new((-128, -128), (128, 128)))
+    }
+
+    #[test]
+    fn same_seed_same_islands() {
+        let islands = generate("determinism");
+        assert!(!islands.is_empty());
+        assert_eq!(summary(&islands), summary(&generate("determinism")));
+    }
+
+    #[test]
+    fn single_biome_is_deterministic() {
+        let biome = read_worldgen_config().remove(0);
+        let rect = || TileRect::new((0, 0), (96, 96));
+        let first = IslandGenerator::with_biome("biome", biome.clone()).generate_islands(rect());
+        let second = IslandGenerator::with_biome("biome", biome).generate_islands(rect());
+        assert_eq!(summary(&first), summary(&second));
+    }
+
+    #[test]
+    fn island_is_the_same_from_any_of_its_tiles() {
+        let generator = IslandGenerator::from_seed("flood fill", read_worldgen_config());
+        for island in generator.generate_islands(TileRect::new((-64, -64), (64, 64))) {
+            let tile = TileRect::new(
+                (island.min_x, island.min_y),
+                (island.max_x + 1, island.max_y + 1),
             )
+            .tiles()
+            .find(|tile| generator.is_land(*tile))
+            .expect("an island without land");
+            let again = generator
+                .generate_island(tile)
+                .expect("land without island");
+            assert_eq!(summary(&[again]), summary(&[island]));
         }
     }
-    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
-    mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
-    mesh.set_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
-    mesh.set_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
-    mesh.set_indices(Some(bevy::render::mesh::Indices::U16(indices)));
-    let rigid_trimesh = if rigid_positions.is_empty() {
-        None
-    } else {
-        Some(TriMesh::new(rigid_positions, rigid_indices))
-    };
-    let friction_trimesh = if friction_positions.is_empty() {
-        None
-    } else {
-        Some(TriMesh::new(friction_positions, friction_indices))
-    };
-    Some(Island {
-        min_x,
-        max_x,
-        min_y,
-        max_y,
-        tiles: Arc::new(tiles_vec),
-        mesh: meshes.add(mesh),
-        entity: None,
-        rigid_trimesh,
-        friction_trimesh,
-        mobs: Vec::new(),
-    })
 }